@@ -91,6 +91,7 @@ impl Photoshoot {
 fn main() -> Result<()> {
 
     //wrap is expecting only digits version output, so handling it before "clap" library starts (Cli::parse();)
+    //older wraps panic on clap's "lumixbackup x.y.z" output, keep this until they have self-updated
     if std::env::args().any(|arg| arg == "--version" || arg == "-V" || arg == "-v") {
         println!("{}", env!("CARGO_PKG_VERSION"));
         return Ok(());
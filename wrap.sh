@@ -1,74 +1,47 @@
 #!/usr/bin/env sh
+# Builds wrap and hands over to 'wrap bootstrap', which creates ~/bin and ~/cli-projects (or the
+# configured bin-dir and build-dir), copies wrap there and puts it on PATH.
+# 'wrap bootstrap --undo' removes the PATH entry.
+set -e
+
+# public key the official wrap.json.sig is made with, printed by 'wrap manifest keygen' on the maintainer's machine
+public_key=""
 
 echo "Welcome to wrap install script."
 
 echo "Checking if Rust Lang is installed"
-
-isRustInstalled=$(which cargo)
-
-if which cargo ; then
-    echo "Rust is installed"
-else
+if ! command -v cargo >/dev/null 2>&1 ; then
     echo "Installing Rust Lang..."
-    #sh <(curl https://sh.rustup.rs) -y
-    curl -s https://sh.rustup.rs | sh /dev/stdin -y
-    source "$HOME/.cargo/env"
-
-    if which cargo ; then
-        echo "Rust is installed"
-    else
-        echo "Something went wrong"
-        exit 1
-    fi
+    curl -sSf https://sh.rustup.rs | sh -s -- -y
+    . "$HOME/.cargo/env"
 fi
+cargo --version
 
-# create foler for excutables
-mkdir -p ~/bin
-# create foler for compiling projects
-mkdir -p ~/cli-projects && cd ~/cli-projects
-
-rm -rf ~/cli-projects/wrap
+work_dir=$(mktemp -d)
+trap 'rm -rf "$work_dir"' EXIT
 
 echo ""
 echo "Downloading 'wrap' project"
-cargo new wrap
-cd wrap
-
-curl -O https://raw.githubusercontent.com/wormaga/wrap-solution/main/wrap/Cargo.toml
-cd ./src
-curl -O https://raw.githubusercontent.com/wormaga/wrap-solution/main/wrap/src/main.rs
+curl -sSfL https://github.com/wormaga/wrap-solution/archive/refs/heads/main.tar.gz | tar xz -C "$work_dir"
 
 echo ""
 echo "Compiling 'wrap' project"
-cd ~/cli-projects/wrap
-cargo build --release
-
-cp ~/cli-projects/wrap/target/release/wrap ~/bin
-
-echo ""
-echo "Ading folder with executables to PATH"
-cd ~
-# adding ~/bin foler to PATH
-if [ -f ".bashrc" ]; then
-  grep -qxF 'export PATH="${HOME}/bin:${PATH}"' ~/.bashrc || echo export PATH=\"\${HOME}/bin:\${PATH}\"  >> ~/.bashrc
-fi
-if [ -n "$BASH_VERSION" ]; then
-  source ~/.bashrc
-fi
-
-if [ -f ".zshrc" ]; then
-  grep -qxF 'export PATH="${HOME}/bin:${PATH}"' ~/.zshrc || echo export PATH=\"\${HOME}/bin:\${PATH}\"  >> ~/.zshrc
-fi
-if [ -n "$ZSH_VERSION" ]; then
-  source ~/.zshrc
+cd "$work_dir"/wrap-solution-main/wrap
+WRAP_BUILTIN_PUBLIC_KEY="$public_key" cargo build --release
+
+./target/release/wrap bootstrap
+# bootstrap copied wrap to the configured bin-dir, ~/bin unless set otherwise
+bin_dir=$(./target/release/wrap config get bin-dir)
+
+if [ -z "$public_key" ] && [ -z "$("$bin_dir/wrap" config get public-key)" ]; then
+    echo ""
+    echo "wrap has no public key to check manifest signatures with. Set the key of your manifest with"
+    echo "  wrap config set public-key <key>"
+    echo "and run 'wrap' to install the tools."
+    exit 0
 fi
 
 # execute 'wrap' cli tool, that will install all other tools
-echo "Starting 'wrap' program"
-~/bin/wrap
-
-
-
 echo ""
-echo "Exiting at $(date)"
-echo ""
\ No newline at end of file
+echo "Starting 'wrap' program"
+"$bin_dir/wrap"
@@ -0,0 +1,9 @@
+use std::env;
+
+fn main() {
+    // the target triple wrap is built for, used to pick prebuilt tool binaries
+    println!("cargo:rustc-env=WRAP_TARGET={}", env::var("TARGET").unwrap());
+    println!("cargo:rerun-if-changed=build.rs");
+    // the key official manifests are signed with, see signature.rs
+    println!("cargo:rerun-if-env-changed=WRAP_BUILTIN_PUBLIC_KEY");
+}
@@ -0,0 +1,365 @@
+use clap::ValueEnum;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{bin_dir, cli_projects_dir, home_dir, xdg_dir};
+use crate::store;
+
+static RUSTUP_INIT: &str = "https://sh.rustup.rs";
+static BLOCK_START: &str = "# >>> wrap >>>";
+static BLOCK_END: &str = "# <<< wrap <<<";
+static BLOCK_NOTE: &str = "# added by 'wrap bootstrap', remove it with 'wrap bootstrap --undo'";
+
+/// Shells whose startup file wrap knows how to extend.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    /// Any other shell that reads ~/.profile
+    Posix,
+}
+
+impl Shell {
+    /// The shell from $SHELL, falling back to a POSIX ~/.profile.
+    fn detect() -> Shell {
+        let shell = env::var("SHELL").unwrap_or_default();
+        match Path::new(&shell).file_name().and_then(|name| name.to_str()) {
+            Some("bash") => Shell::Bash,
+            Some("zsh") => Shell::Zsh,
+            Some("fish") => Shell::Fish,
+            _ => Shell::Posix,
+        }
+    }
+
+    fn profile(self) -> PathBuf {
+        match self {
+            Shell::Bash => home_dir().join(".bashrc"),
+            Shell::Zsh => match env::var_os("ZDOTDIR") {
+                Some(dir) if !dir.is_empty() => PathBuf::from(dir).join(".zshrc"),
+                _ => home_dir().join(".zshrc"),
+            },
+            Shell::Fish => xdg_dir("XDG_CONFIG_HOME", ".config").join("fish").join("config.fish"),
+            Shell::Posix => home_dir().join(".profile"),
+        }
+    }
+
+    /// The line that puts `dir` first on PATH.
+    fn path_line(self, dir: &str) -> String {
+        match self {
+            Shell::Fish => format!("contains -- {} $PATH; or set -gx PATH {} $PATH", dir, dir),
+            _ => format!("export PATH=\"{}:$PATH\"", dir),
+        }
+    }
+}
+
+/// Sets up a machine for wrap: rust, ~/bin and ~/cli-projects, wrap itself in ~/bin, and ~/bin on
+/// PATH in the shell's startup file. Running it again changes nothing.
+pub async fn run(shell: Option<Shell>, install_rust: bool) -> Result<(), Box<dyn Error>> {
+    ensure_rust(install_rust).await?;
+
+    let bin_dir = bin_dir();
+    fs::create_dir_all(&bin_dir)?;
+    fs::create_dir_all(cli_projects_dir())?;
+
+    install_self(&bin_dir)?;
+
+    let shell = shell.unwrap_or_else(Shell::detect);
+    let profile = shell.profile();
+    if add_block(&profile, &path_block(shell, &bin_dir))? {
+        println!("Added {} to PATH in {}", bin_dir.display(), profile.display());
+    } else {
+        println!("{} already puts {} on PATH.", profile.display(), bin_dir.display());
+    }
+
+    println!("Open a new shell, or run 'source {}', to use the tools in {}.", profile.display(), bin_dir.display());
+
+    Ok(())
+}
+
+/// Removes the marked PATH block from every shell startup file wrap knows. Binaries and rust stay.
+pub fn undo() -> Result<(), Box<dyn Error>> {
+    let mut changed = 0;
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Posix] {
+        let profile = shell.profile();
+        if remove_block_from(&profile)? {
+            println!("Removed the PATH entry of wrap from {}", profile.display());
+            changed += 1;
+        }
+    }
+
+    if changed == 0 {
+        println!("No PATH entries of wrap were found.");
+    }
+    println!("{} and the tools in it were left in place.", bin_dir().display());
+
+    Ok(())
+}
+
+/// Installs rust with rustup-init unless cargo is already available.
+async fn ensure_rust(install_rust: bool) -> Result<(), Box<dyn Error>> {
+    if let Ok(output) = Command::new("cargo").arg("--version").output() {
+        if output.status.success() {
+            println!("Rust is installed: {}", String::from_utf8_lossy(&output.stdout).trim());
+            return Ok(());
+        }
+    }
+
+    if !install_rust {
+        eprintln!("Warning: cargo was not found. Install rust from https://rustup.rs to build tools from source.");
+        return Ok(());
+    }
+
+    println!("Installing Rust with rustup...");
+    let script = reqwest::get(RUSTUP_INIT).await?.error_for_status()?.bytes().await?;
+    let script_path = env::temp_dir().join(format!("wrap-rustup-init-{}.sh", std::process::id()));
+    fs::write(&script_path, &script)?;
+
+    let status = Command::new("sh").arg(&script_path).arg("-y").status();
+    let _ = fs::remove_file(&script_path);
+    let status = status.map_err(|e| format!("failed to run rustup-init: {}", e))?;
+    if !status.success() {
+        return Err("rustup-init failed, install rust from https://rustup.rs".into());
+    }
+
+    Ok(())
+}
+
+/// Copies the running wrap into ~/bin, unless it already runs from there.
+fn install_self(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let executable = env::current_exe().map_err(|e| format!("cannot find the wrap executable: {}", e))?;
+    let target = bin_dir.join(crate::SELF_NAME);
+
+    let same = match (executable.canonicalize(), target.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    if !same {
+        store::activate(&executable, &target)?;
+        println!("Copied wrap to {}", target.display());
+    }
+
+    Ok(())
+}
+
+/// `dir` as written in a startup file: relative to $HOME when it is inside it.
+fn shell_path(dir: &Path) -> String {
+    match dir.strip_prefix(home_dir()) {
+        Ok(relative) => format!("$HOME/{}", relative.display()),
+        Err(_) => dir.display().to_string(),
+    }
+}
+
+/// The marked lines that put `bin_dir` on PATH for `shell`.
+fn path_block(shell: Shell, bin_dir: &Path) -> String {
+    format!("{}\n{}\n{}\n{}\n", BLOCK_START, BLOCK_NOTE, shell.path_line(&shell_path(bin_dir)), BLOCK_END)
+}
+
+/// Puts `block` at the end of the profile, in place of an earlier one. Returns false if the profile
+/// already had exactly this block and was left as it is.
+fn add_block(profile: &Path, block: &str) -> Result<bool, Box<dyn Error>> {
+    let content = read_profile(profile)?.unwrap_or_default();
+    let (mut updated, had_block) = remove_block(&content);
+    if had_block && content.contains(block) {
+        return Ok(false);
+    }
+
+    if !updated.is_empty() && !updated.ends_with("\n\n") {
+        updated.push('\n');
+    }
+    updated.push_str(block);
+
+    if let Some(dir) = profile.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_profile(profile, &updated)?;
+
+    Ok(true)
+}
+
+/// Takes the block out of the profile. Returns whether there was one.
+fn remove_block_from(profile: &Path) -> Result<bool, Box<dyn Error>> {
+    let content = match read_profile(profile)? {
+        Some(content) => content,
+        None => return Ok(false),
+    };
+
+    let (mut updated, had_block) = remove_block(&content);
+    if !had_block {
+        return Ok(false);
+    }
+
+    // the empty line put before the block
+    while updated.ends_with("\n\n") {
+        updated.pop();
+    }
+    write_profile(profile, &updated)?;
+
+    Ok(true)
+}
+
+/// The profile's content, None if it does not exist. A profile that cannot be read, or is not
+/// UTF-8, is an error: it must never be taken for an empty one and overwritten.
+fn read_profile(profile: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    match fs::read_to_string(profile) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Err(format!(
+            "{} is not UTF-8 text, leaving it alone. Add {} to PATH in it by hand.",
+            profile.display(),
+            bin_dir().display()
+        )
+        .into()),
+        Err(e) => Err(format!("failed to read {}: {}", profile.display(), e).into()),
+    }
+}
+
+/// Replaces the profile through a temporary file, so that a failed write cannot leave it half written.
+/// A symlinked profile is updated where the link points, with the permissions it had.
+fn write_profile(profile: &Path, content: &str) -> Result<(), Box<dyn Error>> {
+    let profile = fs::canonicalize(profile).unwrap_or_else(|_| profile.to_path_buf());
+    let mut tmp = profile.as_os_str().to_owned();
+    tmp.push(".wrap-tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let written = fs::write(&tmp, content)
+        .and_then(|()| match fs::metadata(&profile) {
+            Ok(metadata) => fs::set_permissions(&tmp, metadata.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|()| fs::rename(&tmp, &profile));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("failed to update {}: {}", profile.display(), e).into());
+    }
+
+    Ok(())
+}
+
+/// The content without the lines from BLOCK_START to BLOCK_END, and whether there were any. A start
+/// marker without its end marker is left alone with everything after it.
+fn remove_block(content: &str) -> (String, bool) {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut result = String::new();
+    let mut found = false;
+
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].trim() == BLOCK_START {
+            let rest = &lines[i + 1..];
+            let end = rest.iter().position(|line| line.trim() == BLOCK_END);
+            let next_start = rest.iter().position(|line| line.trim() == BLOCK_START);
+            if let Some(end) = end.filter(|end| next_start.is_none_or(|start| start > *end)) {
+                found = true;
+                i += end + 2;
+                continue;
+            }
+        }
+
+        result.push_str(lines[i]);
+        result.push('\n');
+        i += 1;
+    }
+
+    (result, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A profile path of its own for every test, in a fresh directory.
+    fn temp_profile(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wrap-bootstrap-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(".bashrc")
+    }
+
+    fn block() -> String {
+        path_block(Shell::Bash, Path::new("/opt/tools/bin"))
+    }
+
+    #[test]
+    fn remove_block_takes_out_only_the_block() {
+        let content = format!("alias ll='ls -l'\n\n{}export EDITOR=vim\n", block());
+        assert_eq!(remove_block(&content), ("alias ll='ls -l'\n\nexport EDITOR=vim\n".to_string(), true));
+
+        let content = "alias ll='ls -l'\n";
+        assert_eq!(remove_block(content), (content.to_string(), false));
+    }
+
+    #[test]
+    fn remove_block_keeps_a_start_marker_without_end() {
+        let content = format!("{}\nalias ll='ls -l'\nexport EDITOR=vim\n", BLOCK_START);
+        assert_eq!(remove_block(&content), (content.clone(), false));
+
+        // the user's lines after a stray start marker survive, the complete block after them does not
+        let content = format!("{}\nalias ll='ls -l'\n{}", BLOCK_START, block());
+        assert_eq!(remove_block(&content), (format!("{}\nalias ll='ls -l'\n", BLOCK_START), true));
+    }
+
+    #[test]
+    fn bootstrap_twice_changes_nothing() {
+        let profile = temp_profile("twice");
+        let original = "alias ll='ls -l'\nexport PATH=\"${HOME}/bin:${PATH}\"\n";
+        fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        fs::write(&profile, original).unwrap();
+
+        assert!(add_block(&profile, &block()).unwrap());
+        let first = fs::read_to_string(&profile).unwrap();
+        assert_eq!(first, format!("{}\n{}", original, block()));
+
+        assert!(!add_block(&profile, &block()).unwrap());
+        assert_eq!(fs::read_to_string(&profile).unwrap(), first);
+
+        fs::remove_dir_all(profile.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn bootstrap_replaces_an_outdated_block() {
+        let profile = temp_profile("outdated");
+        let old = path_block(Shell::Bash, Path::new("/old/bin"));
+
+        assert!(add_block(&profile, &old).unwrap());
+        assert!(add_block(&profile, &block()).unwrap());
+        assert_eq!(fs::read_to_string(&profile).unwrap(), block());
+
+        fs::remove_dir_all(profile.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn undo_restores_the_profile() {
+        let profile = temp_profile("undo");
+        let original = "alias ll='ls -l'\nexport PATH=\"${HOME}/bin:${PATH}\"\n";
+        fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        fs::write(&profile, original).unwrap();
+
+        add_block(&profile, &block()).unwrap();
+        assert!(remove_block_from(&profile).unwrap());
+        assert_eq!(fs::read_to_string(&profile).unwrap(), original);
+
+        assert!(!remove_block_from(&profile).unwrap());
+        assert_eq!(fs::read_to_string(&profile).unwrap(), original);
+
+        fs::remove_dir_all(profile.parent().unwrap()).unwrap();
+        assert!(!remove_block_from(&profile).unwrap());
+    }
+
+    #[test]
+    fn bootstrap_leaves_a_profile_it_cannot_read_alone() {
+        let profile = temp_profile("not-utf8");
+        let original = b"# caf\xe9\nexport FOO=1\n";
+        fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        fs::write(&profile, original).unwrap();
+
+        assert!(add_block(&profile, &block()).is_err());
+        assert!(remove_block_from(&profile).is_err());
+        assert_eq!(fs::read(&profile).unwrap(), original);
+
+        fs::remove_dir_all(profile.parent().unwrap()).unwrap();
+    }
+}
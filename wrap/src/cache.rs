@@ -0,0 +1,302 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::config::{cache_dir, Config};
+use crate::manifest::Checksums;
+
+static MANIFESTS: &str = "manifests"; //~/.cache/wrap/manifests
+static DOWNLOADS: &str = "downloads"; //~/.cache/wrap/downloads
+
+/// A fetched manifest (or signature) and, when it could not be fetched, when the cached copy was.
+pub struct Fetched {
+    pub content: Vec<u8>,
+    /// Set when the content comes from the cache without being revalidated
+    pub cached_at: Option<DateTime<Utc>>,
+}
+
+/// What is stored next to a cached response to revalidate it later.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    fetched_at: DateTime<Utc>,
+}
+
+/// Client for manifests and signatures, with the connect and read timeouts from the config, so that a
+/// network dropping packets ends in the cached copy instead of a hanging run.
+pub struct ManifestClient {
+    client: Client,
+    read_timeout: Duration,
+}
+
+impl ManifestClient {
+    pub fn new(config: &Config) -> Result<ManifestClient, Box<dyn Error>> {
+        let client = Client::builder().connect_timeout(config.connect_timeout()).build()?;
+        Ok(ManifestClient { client, read_timeout: config.read_timeout() })
+    }
+}
+
+/// Fetches `url` through the manifest cache. A cached copy is revalidated with If-None-Match /
+/// If-Modified-Since, used as is when offline, and used with a warning when the server cannot be reached.
+pub async fn fetch(client: &ManifestClient, url: &Url, offline: bool) -> Result<Fetched, Box<dyn Error>> {
+    let (body_path, entry_path) = cache_paths(url);
+    let cached = match (fs::read(&body_path), read_entry(&entry_path)) {
+        (Ok(content), Some(entry)) => Some((content, entry)),
+        _ => None,
+    };
+
+    if offline {
+        return match cached {
+            Some((content, entry)) => Ok(Fetched { content, cached_at: Some(entry.fetched_at) }),
+            None => Err(format!("{} is not cached, run once without --offline", url).into()),
+        };
+    }
+
+    let mut request = client.client.get(url.clone());
+    if let Some((_, entry)) = &cached {
+        if let Some(etag) = &entry.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &entry.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = match timeout(client.read_timeout, request.send()).await {
+        Ok(Ok(response)) if response.status().is_server_error() && cached.is_some() => {
+            Err(format!("server returned {}", response.status()))
+        }
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("timed out".to_string()),
+    };
+
+    let response = match (response, cached) {
+        (Ok(response), Some((content, mut entry))) if response.status() == StatusCode::NOT_MODIFIED => {
+            entry.fetched_at = Utc::now();
+            write_entry(&entry_path, &entry)?;
+            return Ok(Fetched { content, cached_at: None });
+        }
+        (Ok(response), _) => response.error_for_status()?,
+        (Err(e), Some((content, entry))) => {
+            eprintln!("Warning: failed to fetch {} ({}), using the cached copy.", url, e);
+            return Ok(Fetched { content, cached_at: Some(entry.fetched_at) });
+        }
+        (Err(e), None) => return Err(e.into()),
+    };
+
+    let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(String::from);
+    let entry = CacheEntry {
+        url: url.to_string(),
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+        fetched_at: Utc::now(),
+    };
+    let content = timeout(client.read_timeout, response.bytes())
+        .await
+        .map_err(|_| format!("failed to fetch {}: read timed out", url))??
+        .to_vec();
+
+    // a cache that cannot be written only costs a download next time
+    if let Err(e) = store(&body_path, &entry_path, &content, &entry) {
+        eprintln!("Warning: failed to cache {}: {}", url, e);
+    }
+
+    Ok(Fetched { content, cached_at: None })
+}
+
+/// A verified download kept in ~/.cache/wrap/downloads, described by `<key>.json` next to it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedDownload {
+    pub url: String,
+    #[serde(flatten)]
+    pub checksums: Checksums,
+    #[serde(flatten)]
+    pub validators: Validators,
+    pub size: u64,
+    pub cached_at: DateTime<Utc>,
+}
+
+/// Response headers that let the server tell whether a cached download without checksum changed.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// The value for If-Range: a strong ETag, or else Last-Modified. Weak ETags are not allowed there.
+    pub fn if_range(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+/// ~/.cache/wrap/downloads
+pub fn downloads_dir() -> PathBuf {
+    cache_dir().join(DOWNLOADS)
+}
+
+/// The cached copy of `url` and its entry, if one with the same checksums was stored before. A copy
+/// without checksum may be outdated, it has to be revalidated with its validators.
+pub fn cached_download(url: &str, checksums: &Checksums) -> Option<(PathBuf, CachedDownload)> {
+    let (file, entry_path) = download_paths(url, checksums);
+    let entry = serde_json::from_slice(&fs::read(entry_path).ok()?).ok()?;
+    file.exists().then_some((file, entry))
+}
+
+/// Keeps a copy of a verified download.
+pub fn add_download(
+    url: &str,
+    checksums: &Checksums,
+    validators: &Validators,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let (file, entry_path) = download_paths(url, checksums);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = file.with_extension("tmp");
+    fs::copy(path, &tmp)?;
+    fs::rename(&tmp, &file)?;
+
+    let entry = CachedDownload {
+        url: url.to_string(),
+        checksums: checksums.clone(),
+        validators: validators.clone(),
+        size: fs::metadata(&file)?.len(),
+        cached_at: Utc::now(),
+    };
+    fs::write(entry_path, serde_json::to_string_pretty(&entry)?)?;
+
+    Ok(())
+}
+
+/// Drops a cached download, e.g. one that no longer matches its checksum.
+pub fn remove_download(url: &str, checksums: &Checksums) {
+    let (file, entry) = download_paths(url, checksums);
+    let _ = fs::remove_file(file);
+    let _ = fs::remove_file(entry);
+}
+
+/// Everything in the download cache, oldest first.
+pub fn downloads() -> Vec<CachedDownload> {
+    let mut downloads: Vec<CachedDownload> = match fs::read_dir(downloads_dir()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .filter(|path| path.with_extension("").exists())
+            .filter_map(|path| serde_json::from_slice(&fs::read(path).ok()?).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    downloads.sort_by_key(|download| download.cached_at);
+
+    downloads
+}
+
+/// Empties the download cache and returns how many bytes were freed.
+pub fn clean_downloads() -> Result<u64, Box<dyn Error>> {
+    let dir = downloads_dir();
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let size = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    fs::remove_dir_all(&dir)?;
+
+    Ok(size)
+}
+
+/// Human readable size, e.g. "1.2 MB"
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < units.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// Human readable age, e.g. "3 days"
+pub fn format_age(age: TimeDelta) -> String {
+    let (value, unit) = if age.num_days() > 0 {
+        (age.num_days(), "day")
+    } else if age.num_hours() > 0 {
+        (age.num_hours(), "hour")
+    } else {
+        (age.num_minutes().max(0), "minute")
+    };
+
+    format!("{} {}{}", value, unit, if value == 1 { "" } else { "s" })
+}
+
+/// ~/.cache/wrap/manifests/<hash of url>.body and .json
+fn cache_paths(url: &Url) -> (PathBuf, PathBuf) {
+    let key = blake3::hash(url.as_str().as_bytes()).to_hex();
+    let dir = cache_dir().join(MANIFESTS);
+    (dir.join(format!("{}.body", &key[..32])), dir.join(format!("{}.json", &key[..32])))
+}
+
+/// ~/.cache/wrap/downloads/<hash of url and checksums> and .json
+fn download_paths(url: &str, checksums: &Checksums) -> (PathBuf, PathBuf) {
+    let key = format!(
+        "{}\nsha256:{}\nblake3:{}",
+        url,
+        checksums.sha256.as_deref().unwrap_or("").trim().to_lowercase(),
+        checksums.blake3.as_deref().unwrap_or("").trim().to_lowercase()
+    );
+    let key = blake3::hash(key.as_bytes()).to_hex();
+    let dir = downloads_dir();
+
+    (dir.join(&key[..32]), dir.join(format!("{}.json", &key[..32])))
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+fn write_entry(path: &Path, entry: &CacheEntry) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(entry)?)?;
+    Ok(())
+}
+
+fn store(body_path: &Path, entry_path: &Path, content: &[u8], entry: &CacheEntry) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = body_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = body_path.with_extension("tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, body_path)?;
+    write_entry(entry_path, entry)
+}
@@ -0,0 +1,361 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use toml_edit::DocumentMut;
+
+use crate::manifest::DEFAULT_MANIFEST;
+use crate::tap::Tap;
+
+static CONFIG_FILENAME: &str = "config.toml";
+static BIN: &str = "bin"; //~/bin
+static CLI_PROJECTS: &str = "cli-projects"; //~/cli-projects
+static TARGET: &str = "target"; //~/.local/share/wrap/target
+static DEFAULT_KEEP_VERSIONS: usize = 3;
+static DEFAULT_RETRIES: u32 = 3;
+static DEFAULT_CONCURRENCY: usize = 4;
+static DEFAULT_CONNECT_TIMEOUT: u64 = 10; // seconds
+static DEFAULT_READ_TIMEOUT: u64 = 30; // seconds
+
+/// Settings that `wrap config` and WRAP_<KEY> environment variables can change, e.g. WRAP_BIN_DIR for
+/// bin-dir. `registries` is a list of tables and only set in the file.
+pub static KEYS: &[&str] = &[
+    "bin-dir",
+    "build-dir",
+    "manifest",
+    "public-key",
+    "keep-versions",
+    "concurrency",
+    "retries",
+    "connect-timeout",
+    "read-timeout",
+    "toolchain-update",
+    "offline",
+    "from-source",
+];
+
+/// Keys with a true/false value, which also take 1/0, yes/no and on/off
+static BOOLEAN_KEYS: &[&str] = &["toolchain-update", "offline", "from-source"];
+
+// set by Config::load, so the directories do not have to be passed around
+static BIN_DIR: OnceLock<PathBuf> = OnceLock::new();
+static CLI_PROJECTS_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Settings read from ~/.config/wrap/config.toml. Every field is optional.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// Where installed binaries go, ~/bin by default
+    pub bin_dir: Option<String>,
+    /// Where tools are built, ~/cli-projects by default
+    pub build_dir: Option<String>,
+    /// Manifest location (URL, file:// URL, file or directory)
+    pub manifest: Option<String>,
+    /// Extra base64 ed25519 public key trusted for manifest signatures
+    pub public_key: Option<String>,
+    /// How many installed versions of each tool to keep for `wrap rollback`
+    pub keep_versions: Option<usize>,
+    /// How many files of a tool are downloaded at the same time
+    pub concurrency: Option<usize>,
+    /// How many times a failed download is retried
+    pub retries: Option<u32>,
+    /// Seconds to wait for a connection
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for more data while downloading
+    pub read_timeout: Option<u64>,
+    /// Whether wrap may run rustup to install or update a toolchain a tool needs
+    pub toolchain_update: Option<bool>,
+    /// Work from the cached manifests by default, like --offline
+    pub offline: Option<bool>,
+    /// Build from source by default, like --from-source
+    pub from_source: Option<bool>,
+    /// More registries to load tools from, as `[[registries]]` tables
+    pub registries: Option<Vec<Tap>>,
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        config_dir().join(CONFIG_FILENAME)
+    }
+
+    pub fn keep_versions(&self) -> usize {
+        self.keep_versions.unwrap_or(DEFAULT_KEEP_VERSIONS)
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or(DEFAULT_CONCURRENCY).max(1)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(DEFAULT_RETRIES)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT))
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout.unwrap_or(DEFAULT_READ_TIMEOUT))
+    }
+
+    pub fn registries(&self) -> &[Tap] {
+        self.registries.as_deref().unwrap_or_default()
+    }
+
+    pub fn toolchain_update(&self) -> bool {
+        self.toolchain_update.unwrap_or(true)
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline.unwrap_or(false)
+    }
+
+    pub fn build_from_source(&self) -> bool {
+        self.from_source.unwrap_or(false)
+    }
+
+    /// Loads the config file, or the defaults if it does not exist, with WRAP_<KEY> environment
+    /// variables taking precedence over the file. An invalid variable is ignored with a warning.
+    pub fn load() -> Result<Config, Box<dyn Error>> {
+        let mut document = read_document()?;
+        for key in KEYS {
+            let value = match env::var(env_var(key)) {
+                Ok(value) if !value.is_empty() => value,
+                _ => continue,
+            };
+            match check_value(key, &value) {
+                Ok(()) => set_value(&mut document, key, &value),
+                Err(e) => eprintln!("Warning: ignoring {}={}: {}", env_var(key), value, e),
+            }
+        }
+
+        let config = parse_config(&document)?;
+        let _ = BIN_DIR.set(resolve_dir("bin-dir", config.bin_dir.as_deref(), BIN)?);
+        let _ = CLI_PROJECTS_DIR.set(resolve_dir("build-dir", config.build_dir.as_deref(), CLI_PROJECTS)?);
+
+        Ok(config)
+    }
+
+    /// The value of `key` in effect, defaults included.
+    pub fn get(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        let value = match key {
+            "bin-dir" => bin_dir().display().to_string(),
+            "build-dir" => cli_projects_dir().display().to_string(),
+            "manifest" => self.manifest.clone().unwrap_or_else(|| DEFAULT_MANIFEST.to_string()),
+            "public-key" => self.public_key.clone().unwrap_or_default(),
+            "keep-versions" => self.keep_versions().to_string(),
+            "concurrency" => self.concurrency().to_string(),
+            "retries" => self.retries().to_string(),
+            "connect-timeout" => self.connect_timeout().as_secs().to_string(),
+            "read-timeout" => self.read_timeout().as_secs().to_string(),
+            "toolchain-update" => self.toolchain_update().to_string(),
+            "offline" => self.offline().to_string(),
+            "from-source" => self.build_from_source().to_string(),
+            _ => return Err(unknown_key(key).into()),
+        };
+
+        Ok(value)
+    }
+
+    /// Sets `key` in the config file, or removes it with None. Comments and formatting are kept, and
+    /// nothing is written if the result would not load.
+    pub fn set(key: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if !KEYS.contains(&key) {
+            return Err(unknown_key(key).into());
+        }
+
+        let mut document = read_document()?;
+        match value {
+            Some(value) => set_value(&mut document, key, value),
+            None => {
+                document.remove(key);
+            }
+        }
+
+        let config = parse_config(&document)?;
+        resolve_dir("bin-dir", config.bin_dir.as_deref(), BIN)?;
+        resolve_dir("build-dir", config.build_dir.as_deref(), CLI_PROJECTS)?;
+
+        let path = Config::path();
+        fs::create_dir_all(config_dir())?;
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, document.to_string())?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+}
+
+/// The environment variable that overrides `key`, e.g. WRAP_BIN_DIR
+pub fn env_var(key: &str) -> String {
+    format!("WRAP_{}", key.to_uppercase().replace('-', "_"))
+}
+
+fn read_document() -> Result<DocumentMut, Box<dyn Error>> {
+    let path = Config::path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("failed to read {}: {}", path.display(), e).into()),
+    };
+
+    content
+        .parse()
+        .map_err(|e| format!("failed to parse {}: {}", Config::path().display(), e).into())
+}
+
+fn parse_config(document: &DocumentMut) -> Result<Config, Box<dyn Error>> {
+    toml::from_str(&document.to_string()).map_err(|e| {
+        format!("invalid setting in {} or a WRAP_ environment variable: {}", Config::path().display(), e).into()
+    })
+}
+
+/// Replaces the value of `key`, keeping the comment after an existing one. Boolean keys take yes/no and 1/0 too.
+fn set_value(document: &mut DocumentMut, key: &str, text: &str) {
+    let flag = match text.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    };
+    let mut value = match flag {
+        Some(flag) if BOOLEAN_KEYS.contains(&key) => flag.into(),
+        _ => parse_value(text),
+    };
+    if let Some(old) = document.get(key).and_then(|item| item.as_value()) {
+        *value.decor_mut() = old.decor().clone();
+    }
+    document[key] = toml_edit::value(value);
+}
+
+/// Whether `key` set to `text` alone would load, and why not.
+fn check_value(key: &str, text: &str) -> Result<(), String> {
+    let mut document = DocumentMut::new();
+    set_value(&mut document, key, text);
+    let config: Config = toml::from_str(&document.to_string()).map_err(|e| e.message().trim().to_string())?;
+    resolve_dir("bin-dir", config.bin_dir.as_deref(), BIN)?;
+    resolve_dir("build-dir", config.build_dir.as_deref(), CLI_PROJECTS)?;
+
+    Ok(())
+}
+
+/// A value as written in TOML (8, true, "text"), anything else as a plain string.
+fn parse_value(text: &str) -> toml_edit::Value {
+    let parsed = format!("value = {}", text).parse::<DocumentMut>();
+    match parsed.ok().and_then(|document| document.get("value")?.as_value().cloned()) {
+        Some(mut value) => {
+            value.decor_mut().clear();
+            value
+        }
+        None => text.into(),
+    }
+}
+
+/// `~/dir` or an absolute path, `default` in the home directory if not set.
+fn resolve_dir(key: &str, value: Option<&str>, default: &str) -> Result<PathBuf, String> {
+    let path = match value {
+        None => return Ok(home_dir().join(default)),
+        Some("~") => home_dir(),
+        Some(value) => match value.strip_prefix("~/") {
+            Some(relative) => home_dir().join(relative),
+            None => PathBuf::from(value),
+        },
+    };
+
+    if !path.is_absolute() {
+        return Err(format!("{} must be an absolute path or start with ~/, got {}", key, path.display()));
+    }
+
+    Ok(path)
+}
+
+fn unknown_key(key: &str) -> String {
+    format!("unknown setting {}, known settings are {}", key, KEYS.join(", "))
+}
+
+/// Where installed binaries go, ~/bin unless bin-dir is set
+pub fn bin_dir() -> PathBuf {
+    BIN_DIR.get().cloned().unwrap_or_else(|| home_dir().join(BIN))
+}
+
+/// Where tools are built, ~/cli-projects unless build-dir is set
+pub fn cli_projects_dir() -> PathBuf {
+    CLI_PROJECTS_DIR.get().cloned().unwrap_or_else(|| home_dir().join(CLI_PROJECTS))
+}
+
+/// CARGO_TARGET_DIR shared by all builds, so dependencies are compiled once
+pub fn target_dir() -> PathBuf {
+    data_dir().join(TARGET)
+}
+
+/// $XDG_CONFIG_HOME/wrap, defaulting to ~/.config/wrap
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("wrap")
+}
+
+/// $XDG_DATA_HOME/wrap, defaulting to ~/.local/share/wrap
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share").join("wrap")
+}
+
+/// $XDG_CACHE_HOME/wrap, defaulting to ~/.cache/wrap
+pub fn cache_dir() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache").join("wrap")
+}
+
+pub fn home_dir() -> PathBuf {
+    dirs::home_dir().expect("failed to get home directory")
+}
+
+/// $<var>, defaulting to ~/<default>
+pub fn xdg_dir(var: &str, default: &str) -> PathBuf {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir().join(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_value_takes_words_for_booleans() {
+        let mut document = DocumentMut::new();
+        let cases = [("1", true), ("yes", true), ("On", true), ("0", false), ("no", false), ("false", false)];
+        for (text, expected) in cases {
+            set_value(&mut document, "offline", text);
+            assert_eq!(document["offline"].as_bool(), Some(expected), "{}", text);
+        }
+
+        // only boolean keys are turned into true/false
+        set_value(&mut document, "concurrency", "1");
+        assert_eq!(document["concurrency"].as_integer(), Some(1));
+        set_value(&mut document, "manifest", "yes");
+        assert_eq!(document["manifest"].as_str(), Some("yes"));
+    }
+
+    #[test]
+    fn set_value_keeps_the_comment() {
+        let mut document: DocumentMut = "retries = 3 # flaky network\n".parse().unwrap();
+        set_value(&mut document, "retries", "5");
+        assert_eq!(document.to_string(), "retries = 5 # flaky network\n");
+    }
+
+    #[test]
+    fn check_value_rejects_what_would_not_load() {
+        assert!(check_value("offline", "1").is_ok());
+        assert!(check_value("concurrency", "8").is_ok());
+        assert!(check_value("bin-dir", "~/tools").is_ok());
+        assert!(check_value("manifest", "https://example.com/wrap.json").is_ok());
+
+        // WRAP_OFFLINE=maybe or WRAP_CONCURRENCY=many are ignored by Config::load
+        assert!(check_value("offline", "maybe").is_err());
+        assert!(check_value("concurrency", "many").is_err());
+        assert!(check_value("retries", "-1").is_err());
+        assert!(check_value("bin-dir", "tools").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{bin_dir, cli_projects_dir};
+use crate::registry::{self, Registry};
+use crate::store;
+
+/// Collects the results of the checks, printing each one as it comes.
+#[derive(Default)]
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: &str) {
+        println!("[ok] {}", message);
+    }
+
+    fn problem(&mut self, message: &str, fix: &str) {
+        self.problems += 1;
+        println!("[!!] {}", message);
+        println!("     fix: {}", fix);
+    }
+}
+
+/// Checks the environment wrap depends on and prints a fix for every problem found.
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let mut report = Report::default();
+
+    check_rust(&mut report);
+    check_bin_dir(&mut report);
+    let registry = check_registry(&mut report);
+    if let Some(registry) = &registry {
+        check_shadowed(&mut report, registry);
+    }
+    check_projects(&mut report, registry.as_ref());
+
+    if report.problems > 0 {
+        return Err(format!("{} problem(s) found", report.problems).into());
+    }
+
+    println!("No problems found.");
+    Ok(())
+}
+
+fn check_rust(report: &mut Report) {
+    for program in ["cargo", "rustc", "rustup"] {
+        match program_version(program) {
+            Some(version) => report.ok(&version),
+            None => report.problem(
+                &format!("{} was not found", program),
+                "install rust with rustup from https://rustup.rs and open a new shell",
+            ),
+        }
+    }
+}
+
+fn check_bin_dir(report: &mut Report) {
+    let bin_dir = bin_dir();
+    if !bin_dir.is_dir() {
+        report.problem(
+            &format!("{} does not exist", bin_dir.display()),
+            &format!("create it with 'mkdir -p {}', wrap also creates it on the first install", bin_dir.display()),
+        );
+    }
+
+    if path_dirs().iter().any(|dir| same_dir(dir, &bin_dir)) {
+        report.ok(&format!("{} is on PATH", bin_dir.display()));
+    } else {
+        report.problem(
+            &format!("{} is not on PATH in this shell", bin_dir.display()),
+            "run 'wrap bootstrap' and open a new shell",
+        );
+    }
+}
+
+/// Compares every registry entry with the binary on disk. Returns the registry if it could be read.
+fn check_registry(report: &mut Report) -> Option<Registry> {
+    let registry = match Registry::load() {
+        Ok(registry) => registry,
+        Err(e) => {
+            report.problem(
+                &format!("the install registry cannot be read: {}", e),
+                &format!("fix or delete {}, then reinstall your tools", Registry::path().display()),
+            );
+            return None;
+        }
+    };
+
+    let mut healthy = 0;
+    for installed in registry.tools() {
+        let name = &installed.name;
+        if !installed.binary.exists() {
+            report.problem(
+                &format!("{} {} is registered, but {} is missing", name, installed.version, installed.binary.display()),
+                &format!("run 'wrap install {}' or 'wrap rollback {} {}'", name, name, installed.version),
+            );
+            continue;
+        }
+
+        match registry::hash_file(&installed.binary) {
+            Ok(hash) if hash == installed.binary_hash => healthy += 1,
+            _ => report.problem(
+                &format!("{} was changed after wrap installed {}", installed.binary.display(), installed.version),
+                &format!("reinstall it with 'wrap install {}@{}'", name, installed.version),
+            ),
+        }
+
+        if let Ok(version) = semver::Version::parse(&installed.version) {
+            if !store::binary_path(name, &version).exists() {
+                report.problem(
+                    &format!("the kept copy of {} {} is missing, rollback to it will not work", name, installed.version),
+                    &format!("reinstall it with 'wrap install {}@{}'", name, installed.version),
+                );
+            }
+        }
+    }
+
+    if healthy == registry.tools().len() {
+        report.ok(&format!("all {} registered tools match their binaries", healthy));
+    }
+
+    Some(registry)
+}
+
+/// Finds other binaries with the name of a wrap-installed tool that come first on PATH.
+fn check_shadowed(report: &mut Report, registry: &Registry) {
+    let dirs = path_dirs();
+    for installed in registry.tools() {
+        let first = dirs
+            .iter()
+            .map(|dir| dir.join(&installed.name))
+            .find(|candidate| candidate.is_file());
+
+        if let Some(first) = first {
+            if !same_file(&first, &installed.binary) {
+                report.problem(
+                    &format!("{} shadows {} installed by wrap", first.display(), installed.binary.display()),
+                    &format!(
+                        "remove {} if it is stale, or put {} earlier on PATH",
+                        first.display(),
+                        bin_dir().display()
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Looks for leftovers of interrupted installs and project folders that cannot be built.
+fn check_projects(report: &mut Report, registry: Option<&Registry>) {
+    let entries = match fs::read_dir(cli_projects_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let mut problems = 0;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !path.is_dir() {
+            continue;
+        }
+
+        if name.starts_with('.') && (name.ends_with(".staging") || name.ends_with(".download")) {
+            problems += 1;
+            report.problem(
+                &format!("{} is left over from an interrupted install", path.display()),
+                "run 'wrap clean'",
+            );
+        } else if !path.join("Cargo.toml").exists() {
+            problems += 1;
+            report.problem(
+                &format!("{} has no Cargo.toml", path.display()),
+                &format!("delete it with 'rm -rf {}' and reinstall {} if you use it", path.display(), name),
+            );
+        } else if name != crate::SELF_NAME && registry.is_some_and(|registry| registry.get(&name).is_none()) {
+            problems += 1;
+            report.problem(
+                &format!("{} belongs to no tool installed by wrap", path.display()),
+                &format!("run 'wrap install {}', or delete the folder if it is no longer needed", name),
+            );
+        }
+    }
+
+    if problems == 0 {
+        report.ok(&format!("no broken project folders in {}", cli_projects_dir().display()));
+    }
+}
+
+/// First line of `<program> --version`, None if it cannot be run.
+fn program_version(program: &str) -> Option<String> {
+    let output = Command::new(program).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout).lines().next().map(|line| line.trim().to_string())
+}
+
+fn path_dirs() -> Vec<PathBuf> {
+    env::var_os("PATH").map(|path| env::split_paths(&path).collect()).unwrap_or_default()
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
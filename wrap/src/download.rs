@@ -0,0 +1,280 @@
+use futures::stream::{self, StreamExt};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, StatusCode};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::time::{sleep, timeout};
+
+use crate::cache::{self, Validators};
+use crate::config::Config;
+use crate::error::WrapError;
+use crate::manifest::Checksums;
+
+static FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Streams files to disk, retrying failed attempts with exponential backoff.
+pub struct Downloader {
+    client: Client,
+    retries: u32,
+    read_timeout: Duration,
+    concurrency: usize,
+    /// Refuse every network download (--offline)
+    offline: bool,
+}
+
+/// A failed download attempt. Network errors, timeouts and 5xx/429 responses are worth retrying.
+#[derive(Debug)]
+struct AttemptError {
+    message: String,
+    retryable: bool,
+}
+
+impl AttemptError {
+    fn retryable(message: impl ToString) -> AttemptError {
+        AttemptError { message: message.to_string(), retryable: true }
+    }
+
+    fn fatal(message: impl ToString) -> AttemptError {
+        AttemptError { message: message.to_string(), retryable: false }
+    }
+}
+
+impl fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for AttemptError {}
+
+/// How a fetch ended: with the file downloaded, or with the server confirming the cached copy.
+enum Fetch {
+    Downloaded(Validators),
+    NotModified,
+}
+
+impl Downloader {
+    pub fn new(config: &Config, offline: bool) -> Result<Downloader, Box<dyn Error>> {
+        let client = Client::builder()
+            .connect_timeout(config.connect_timeout())
+            .build()?;
+
+        Ok(Downloader {
+            client,
+            retries: config.retries(),
+            read_timeout: config.read_timeout(),
+            concurrency: config.concurrency(),
+            offline,
+        })
+    }
+
+    /// Downloads all `(url, checksums, target)`, at most `concurrency` at a time. The results are
+    /// returned in the same order as the downloads.
+    pub async fn download_all(&self, downloads: Vec<(&str, &Checksums, PathBuf)>) -> Vec<Result<(), Box<dyn Error>>> {
+        stream::iter(downloads)
+            .map(|(url, checksums, target)| async move { self.download(url, checksums, &target).await })
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+
+    /// Downloads `url` to `target` and checks it against the checksums. Downloads are added to the
+    /// download cache once verified. A cached download with a checksum is used as is, one without is
+    /// only used when the server says it did not change, or when offline.
+    pub async fn download(&self, url: &str, checksums: &Checksums, target: &Path) -> Result<(), Box<dyn Error>> {
+        let mut cached = cache::cached_download(url, checksums);
+        if let Some((file, _)) = &cached {
+            if !checksums.is_empty() || self.offline {
+                fs::copy(file, target)?;
+                if checksums.verify(target, url).is_ok() {
+                    progress!("Using cached {}", url);
+                    return Ok(());
+                }
+                // a damaged cache entry is replaced by a fresh download
+                cache::remove_download(url, checksums);
+                cached = None;
+            }
+        }
+
+        let validators = cached.as_ref().map(|(_, entry)| &entry.validators);
+        let fetched = self
+            .fetch(url, target, validators)
+            .await
+            .map_err(|e| WrapError::Download(format!("failed to download {}: {}", url, e)))?;
+
+        match (fetched, cached) {
+            (Fetch::NotModified, Some((file, _))) => {
+                fs::copy(file, target)?;
+                progress!("Using cached {}, it did not change", url);
+            }
+            (Fetch::NotModified, None) => {
+                return Err(WrapError::Download(format!("failed to download {}: unexpected 304 Not Modified", url)).into());
+            }
+            (Fetch::Downloaded(validators), _) => {
+                checksums.verify(target, url).map_err(WrapError::Checksum)?;
+
+                if is_remote(url) {
+                    if let Err(e) = cache::add_download(url, checksums, &validators, target) {
+                        eprintln!("Warning: failed to cache {}: {}", url, e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches `url` to `target`. Data goes to `<target>.part` first, which lets a retry resume
+    /// with a Range request, and is renamed to `target` once complete. With the `validators` of a
+    /// cached copy the server may answer that it did not change instead. Local paths and `file://`
+    /// urls are copied.
+    async fn fetch(&self, url: &str, target: &Path, validators: Option<&Validators>) -> Result<Fetch, Box<dyn Error>> {
+        if !is_remote(url) {
+            let path = url.strip_prefix("file://").unwrap_or(url);
+            fs::copy(path, target)?;
+            return Ok(Fetch::Downloaded(Validators::default()));
+        }
+
+        if self.offline {
+            return Err("not available offline".into());
+        }
+
+        let part = part_path(target);
+        if part.exists() {
+            fs::remove_file(&part)?;
+        }
+
+        // validators of the response the data in `part` comes from
+        let mut started = None;
+        let mut attempt = 0;
+        loop {
+            match self.try_download(url, &part, validators, &mut started).await {
+                Ok(Fetch::NotModified) => return Ok(Fetch::NotModified),
+                Ok(fetched) => {
+                    fs::rename(&part, target)?;
+                    return Ok(fetched);
+                }
+                Err(e) if e.retryable && attempt < self.retries => {
+                    let delay = FIRST_RETRY_DELAY * 2u32.pow(attempt);
+                    attempt += 1;
+                    eprintln!(
+                        "Downloading {} failed: {}. Retrying in {}s ({}/{})",
+                        url,
+                        e,
+                        delay.as_secs(),
+                        attempt,
+                        self.retries
+                    );
+                    sleep(delay).await;
+                }
+                Err(e) => {
+                    let _ = fs::remove_file(&part);
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    /// One attempt. `started` holds the validators of the response the data in `part` comes from: a
+    /// resumed download sends them as If-Range, so that a file changed in between is sent in full
+    /// instead of being joined to the old part. Without one the download starts over.
+    async fn try_download(
+        &self,
+        url: &str,
+        part: &Path,
+        validators: Option<&Validators>,
+        started: &mut Option<Validators>,
+    ) -> Result<Fetch, AttemptError> {
+        // continue where the previous attempt stopped
+        let mut offset = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+        let if_range = started.as_ref().and_then(Validators::if_range);
+        if offset > 0 && if_range.is_none() {
+            let _ = fs::remove_file(part);
+            offset = 0;
+        }
+
+        let mut request = self.client.get(url);
+        if let (true, Some(if_range)) = (offset > 0, if_range) {
+            request = request.header(RANGE, format!("bytes={}-", offset)).header(IF_RANGE, if_range);
+        } else if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let mut response = request.send().await.map_err(AttemptError::retryable)?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED && validators.is_some() {
+            return Ok(Fetch::NotModified);
+        }
+
+        let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(String::from);
+        let received = Validators { etag: header(ETAG), last_modified: header(LAST_MODIFIED) };
+
+        let append = match status {
+            StatusCode::PARTIAL_CONTENT if offset > 0 => true,
+            // the server ignored the range, start over
+            status if status.is_success() => false,
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                let _ = fs::remove_file(part);
+                return Err(AttemptError::retryable("server could not resume the download"));
+            }
+            status if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+                return Err(AttemptError::retryable(format!("server returned {}", status)));
+            }
+            status => return Err(AttemptError::fatal(format!("server returned {}", status))),
+        };
+        if !append {
+            *started = Some(received);
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(part)
+            .await
+            .map_err(|e| AttemptError::fatal(format!("failed to write {}: {}", part.display(), e)))?;
+
+        loop {
+            let chunk = timeout(self.read_timeout, response.chunk())
+                .await
+                .map_err(|_| AttemptError::retryable("read timed out"))?
+                .map_err(AttemptError::retryable)?;
+
+            match chunk {
+                Some(bytes) => file
+                    .write_all(&bytes)
+                    .await
+                    .map_err(|e| AttemptError::fatal(format!("failed to write {}: {}", part.display(), e)))?,
+                None => break,
+            }
+        }
+
+        file.flush()
+            .await
+            .map_err(|e| AttemptError::fatal(format!("failed to write {}: {}", part.display(), e)))?;
+
+        Ok(Fetch::Downloaded(started.clone().unwrap_or_default()))
+    }
+}
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+fn part_path(target: &Path) -> PathBuf {
+    let mut part = target.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
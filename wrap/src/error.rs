@@ -0,0 +1,102 @@
+use std::error::Error;
+use std::fmt;
+
+/// Exit code for errors without a kind of their own, e.g. an unreadable config file
+pub static EXIT_OTHER: i32 = 1;
+
+/// The ways wrap can fail that a script may want to tell apart, each with its own exit code.
+/// Functions return `Box<dyn Error>` as usual; errors are classified where they happen and main
+/// picks the exit code with [`exit_code`].
+#[derive(Debug)]
+pub enum WrapError {
+    /// Bad command line, an unknown tool or no release matching the requested version
+    Usage(String),
+    /// A manifest could not be fetched, neither from the network nor from the cache
+    ManifestFetch(String),
+    /// A manifest could not be parsed, or its signature is missing or does not verify
+    ManifestParse(String),
+    /// A file, archive, binary or git repository could not be downloaded
+    Download(String),
+    /// A download does not match the checksum (or commit) in the manifest
+    Checksum(String),
+    /// The toolchain could not be prepared or cargo failed
+    Build(String),
+    /// The built tool could not be put in place or recorded
+    Install(String),
+    /// A version could not be parsed or detected
+    VersionDetection(String),
+    /// A file wrap did not install, or that was changed since, is left alone
+    Refused(String),
+    /// Some of the selected tools failed, each for its own reason
+    Tools(Vec<(String, WrapError)>),
+}
+
+impl WrapError {
+    /// Takes a WrapError out of `error`, using `kind` for an error that was not classified yet.
+    pub fn classify(error: Box<dyn Error>, kind: fn(String) -> WrapError) -> WrapError {
+        match error.downcast::<WrapError>() {
+            Ok(error) => *error,
+            Err(error) => kind(error.to_string()),
+        }
+    }
+
+    /// 2 is also what clap exits with for invalid arguments.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WrapError::Usage(_) => 2,
+            WrapError::ManifestFetch(_) => 3,
+            WrapError::ManifestParse(_) => 4,
+            WrapError::Download(_) => 5,
+            WrapError::Checksum(_) => 6,
+            WrapError::Build(_) => 7,
+            WrapError::Install(_) => 8,
+            WrapError::VersionDetection(_) => 9,
+            WrapError::Refused(_) => 10,
+            // the first failure decides
+            WrapError::Tools(failures) => failures.first().map(|(_, error)| error.exit_code()).unwrap_or(EXIT_OTHER),
+        }
+    }
+
+    /// Short name of the kind of failure, for the summary table
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WrapError::Usage(_) => "usage",
+            WrapError::ManifestFetch(_) => "manifest fetch",
+            WrapError::ManifestParse(_) => "manifest parse",
+            WrapError::Download(_) => "download",
+            WrapError::Checksum(_) => "checksum",
+            WrapError::Build(_) => "build",
+            WrapError::Install(_) => "install",
+            WrapError::VersionDetection(_) => "version",
+            WrapError::Refused(_) => "refused",
+            WrapError::Tools(_) => "tools",
+        }
+    }
+}
+
+impl fmt::Display for WrapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WrapError::Usage(message)
+            | WrapError::ManifestFetch(message)
+            | WrapError::ManifestParse(message)
+            | WrapError::Download(message)
+            | WrapError::Checksum(message)
+            | WrapError::Build(message)
+            | WrapError::Install(message)
+            | WrapError::VersionDetection(message)
+            | WrapError::Refused(message) => write!(f, "{}", message),
+            WrapError::Tools(failures) => {
+                let names: Vec<&str> = failures.iter().map(|(name, _)| name.as_str()).collect();
+                write!(f, "failed to install {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for WrapError {}
+
+/// The exit code for an error returned by `run`.
+pub fn exit_code(error: &(dyn Error + 'static)) -> i32 {
+    error.downcast_ref::<WrapError>().map(WrapError::exit_code).unwrap_or(EXIT_OTHER)
+}
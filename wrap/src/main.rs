@@ -1,303 +1,1113 @@
-use reqwest::{Client, Error};
+use clap::{Parser, Subcommand};
+use chrono::Utc;
+use cache::ManifestClient;
+use config::{bin_dir, cli_projects_dir, target_dir, Config};
+use download::Downloader;
+use error::WrapError;
+use manifest::{Product, Release, Tool, ToolSource, ToolState};
+use registry::{InstalledTool, Registry};
 use semver::Version;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::env;
+use std::error::Error;
 use std::fs;
-use std::fs::File;
 use std::io;
+use std::io::Write;
 use std::path::Path;
 use std::process;
+use std::path::PathBuf;
 use std::process::Command;
+use tap::Tap;
 extern crate reqwest;
 
-static BIN: &str = "bin"; //~/bin
-static CLI_PROJECTS: &str = "cli-projects"; //~/cli-projects
+/// Name of wrap's own project folder in ~/cli-projects and of its kept versions.
+static SELF_NAME: &str = "wrap";
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Product {
-    last_update: String,
-    tools: Vec<Tool>,
+// first, so that progress! is available in the other modules
+#[macro_use]
+mod output;
+
+mod bootstrap;
+mod cache;
+mod config;
+mod doctor;
+mod download;
+mod error;
+mod manifest;
+mod registry;
+mod signature;
+mod source;
+mod store;
+mod tap;
+mod toolchain;
+mod version;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Manifest to install from: http(s) URL, file:// URL, local file or directory containing wrap.json
+    #[arg(short, long, global = true, env = "WRAP_MANIFEST", value_name = "SOURCE")]
+    manifest: Option<String>,
+
+    /// Load the manifest even if it is unsigned or its signature does not verify
+    #[arg(long, global = true)]
+    insecure: bool,
+
+    /// Use only cached manifests and never download; installs reuse versions kept for rollback
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Never run rustup, even if a tool needs a newer rust or another toolchain
+    #[arg(long, global = true)]
+    no_toolchain_update: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Tool {
-    name: String,
-    version: String,
-    files: Vec<Asset>,
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Install one or more tools by name (reinstalls only if an update is available)
+    Install {
+        /// Tool name, optionally with a version: litegallery@0.3.1 or litegallery@^0.3
+        #[arg(value_name = "TOOL", required = true)]
+        tools: Vec<String>,
+
+        /// Build from source even if the manifest has a prebuilt binary for this platform
+        #[arg(long)]
+        from_source: bool,
+
+        /// Print one JSON record per tool instead of the summary, progress goes to stderr
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Update the given installed tools, or all unpinned ones with --all
+    Update {
+        #[arg(value_name = "TOOL", required_unless_present = "all", conflicts_with = "all")]
+        tools: Vec<String>,
+
+        /// Update every installed tool that has a newer version, except pinned ones
+        #[arg(short, long)]
+        all: bool,
+
+        /// Build from source even if the manifest has a prebuilt binary for this platform
+        #[arg(long)]
+        from_source: bool,
+    },
+
+    /// List all available tools and their install status
+    List {
+        /// Print the tools as JSON records
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List installed tools that have a newer version available
+    Outdated {
+        /// Print the tools as JSON records
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Remove a tool installed by wrap: its binary, build directory and registry entry
+    Uninstall {
+        #[arg(value_name = "TOOL")]
+        tool: String,
+
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Keep a tool at its installed version: update --all skips it
+    Pin {
+        #[arg(value_name = "TOOL")]
+        tool: String,
+    },
+
+    /// Let update --all update a pinned tool again
+    Unpin {
+        #[arg(value_name = "TOOL")]
+        tool: String,
+    },
+
+    /// Switch a tool back to a previously installed version (the one before the current by default)
+    Rollback {
+        #[arg(value_name = "TOOL")]
+        tool: String,
+
+        #[arg(value_name = "VERSION")]
+        version: Option<String>,
+    },
+
+    /// Update wrap itself to the version in the default manifest
+    SelfUpdate {
+        /// Build from source even if the manifest has a prebuilt binary for this platform
+        #[arg(long)]
+        from_source: bool,
+    },
+
+    /// Delete build output (the shared cargo target directory) to free disk space
+    Clean,
+
+    /// Set up rust, ~/bin and ~/cli-projects, copy wrap to ~/bin and add ~/bin to PATH in the shell profile
+    Bootstrap {
+        /// Shell whose startup file gets the PATH entry, detected from $SHELL by default
+        #[arg(long, value_enum)]
+        shell: Option<bootstrap::Shell>,
+
+        /// Remove the PATH entries added by wrap bootstrap
+        #[arg(long, conflicts_with = "shell")]
+        undo: bool,
+    },
+
+    /// Check the rust toolchain, ~/bin, PATH and installed tools, and suggest fixes for problems
+    Doctor,
+
+    /// Manage the download cache in ~/.cache/wrap
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Show or change settings in ~/.config/wrap/config.toml
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Manifest maintenance: signing keys and signatures
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommands,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Asset {
-    location: String,
-    filename: String,
-    url: String,
+#[derive(Subcommand, Debug)]
+enum CacheCommands {
+    /// List cached downloads
+    List,
+
+    /// Delete all cached downloads
+    Clean,
 }
 
-impl Tool {
-    pub fn is_installed(&self) -> bool {
-        Command::new(&self.name)
-        .arg("--version")
-        .output()
-        .is_ok()
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print the value in effect for a setting, or for all settings
+    Get {
+        #[arg(value_name = "KEY")]
+        key: Option<String>,
+    },
+
+    /// Write a setting to the config file, e.g. wrap config set bin-dir ~/.local/bin
+    Set {
+        #[arg(value_name = "KEY")]
+        key: String,
+
+        #[arg(value_name = "VALUE")]
+        value: String,
+    },
+
+    /// Remove a setting from the config file, so its default applies again
+    Unset {
+        #[arg(value_name = "KEY")]
+        key: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ManifestCommands {
+    /// Generate a private signing key and print its public key
+    Keygen {
+        /// Where to write the private key [default: ~/.config/wrap/signing.key]
+        #[arg(short, long, value_name = "PATH")]
+        key: Option<PathBuf>,
+    },
+
+    /// Write a detached signature (<MANIFEST>.sig) for a local manifest
+    Sign {
+        #[arg(value_name = "MANIFEST", default_value = "wrap.json")]
+        file: PathBuf,
+
+        /// Private key to sign with [default: ~/.config/wrap/signing.key]
+        #[arg(short, long, value_name = "PATH")]
+        key: Option<PathBuf>,
+    },
+}
+
+/// How `install_tools` gets the tools.
+#[derive(Debug, Clone, Copy)]
+struct InstallOptions {
+    /// Build even if there is a prebuilt binary
+    from_source: bool,
+    /// Do not download anything
+    offline: bool,
+    /// Allow rustup to install or update toolchains
+    toolchain_update: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {}", e);
+        process::exit(error::exit_code(&*e));
     }
+}
 
-    pub fn is_update_available(&self) -> bool {
-        if !self.is_installed() {
-            return true;
-        }
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    // with --json stdout is kept for the result
+    output::set_json(matches!(
+        cli.command,
+        Some(Commands::Install { json: true, .. })
+            | Some(Commands::List { json: true })
+            | Some(Commands::Outdated { json: true })
+    ));
 
-        let output = Command::new(&self.name)
-        .arg("--version")
-        .output()
-        .unwrap();
+    // a broken config file can still be fixed with set and unset
+    match cli.command {
+        Some(Commands::Config { command: ConfigCommands::Set { key, value } }) => return set_config(&key, Some(&value)),
+        Some(Commands::Config { command: ConfigCommands::Unset { key } }) => return set_config(&key, None),
+        _ => {}
+    }
 
-        let stdout = String::from_utf8(output.stdout).unwrap();
+    let config = Config::load()?;
 
-        let installed_version = Version::parse(stdout.trim()).unwrap();
-        let latest_version = Version::parse(&self.version).unwrap();
+    // flags on the command line add to the defaults from the config file
+    let defaults = InstallOptions {
+        from_source: config.build_from_source(),
+        offline: cli.offline || config.offline(),
+        toolchain_update: !cli.no_toolchain_update && config.toolchain_update(),
+    };
 
-        if installed_version > latest_version {
-            println!("Something is wrong, installed {}, the latest available is {}.", installed_version, latest_version);
+    // these commands work on local files only and do not need a loaded manifest
+    match cli.command {
+        Some(Commands::Manifest { command }) => return run_manifest_command(command),
+        Some(Commands::Config { command: ConfigCommands::Get { key } }) => return print_config(&config, key.as_deref()),
+        Some(Commands::Cache { command }) => return run_cache_command(command),
+        Some(Commands::Clean) => return clean_build_output(),
+        Some(Commands::Doctor) => return doctor::run(),
+        Some(Commands::Bootstrap { undo: true, .. }) => return bootstrap::undo(),
+        Some(Commands::Bootstrap { shell, .. }) => {
+            return bootstrap::run(shell, !defaults.offline && defaults.toolchain_update).await;
         }
+        Some(Commands::Uninstall { tool, yes }) => return uninstall_tool(&tool, yes),
+        Some(Commands::Rollback { tool, version }) => return rollback_tool(&tool, version.as_deref()),
+        Some(Commands::Pin { tool }) => return pin_tool(&tool, true),
+        Some(Commands::Unpin { tool }) => return pin_tool(&tool, false),
+        _ => {}
+    }
+
+    let taps = tap::taps(&config, cli.manifest)?;
 
-        installed_version < latest_version
+    let trusted_keys = signature::trusted_keys(&config)?;
+    if trusted_keys.is_empty() && !cli.insecure {
+        let message = format!(
+            "no public key to verify manifests with, set public-key in {} or pass --insecure",
+            Config::path().display()
+        );
+        return Err(WrapError::ManifestParse(message).into());
     }
+    let trusted_keys = if cli.insecure { None } else { Some(trusted_keys.as_slice()) };
 
-    pub fn install_description(&self) -> String {
-        if !self.is_installed() {
-            return "".to_string();
-        }
+    let client = ManifestClient::new(&config)?;
+    let product: Product = tap::load_all(&taps, &client, trusted_keys, defaults.offline).await?;
+    //println!("{:#?}", product); //debug
 
-        let output = Command::new(&self.name)
-            .arg("--version")
-            .output()
-            .unwrap();
+    let mut registry = Registry::load()?;
 
-        let stdout = String::from_utf8(output.stdout).unwrap();
-        let stdout = stdout.trim();
+    if !matches!(cli.command, Some(Commands::SelfUpdate { .. })) {
+        notify_self_update(&product);
+    }
 
-        let installed_version = stdout
-            .split_whitespace() // split by spaces
-            .find_map(|s| Version::parse(s).ok()) // take the first valid semver
-            .expect(&format!("Failed to parse instslled tool version from output: {}", stdout));
+    match cli.command {
+        None => {
+            let selected_tools: Vec<(&Tool, &Release)> = product
+                .filter_tools_by_user(&registry)
+                .into_iter()
+                .map(|tool| (tool, tool.latest()))
+                .collect();
 
-        let latest_version = Version::parse(&self.version).unwrap();
+            if selected_tools.is_empty() {
+                println!("No tools were selected. Exiting the program.");
+                return Ok(());
+            }
 
-        if installed_version < latest_version {
-            return format!("(update available: {}-->{})", installed_version, latest_version);
+            install_tools(selected_tools, &taps, &config, defaults, &mut registry).await?;
         }
-
-        if installed_version == latest_version {
-            return format!("(Latest installed {})", latest_version);
+        Some(Commands::Install { tools, from_source, .. }) => {
+            let options = InstallOptions { from_source: from_source || defaults.from_source, ..defaults };
+            let selected_tools = match product.find_tools(&tools, &registry) {
+                Ok(selected_tools) => selected_tools,
+                Err(e) => {
+                    // stdout still gets a record for every tool that could not be looked up
+                    if output::is_json() {
+                        let records: Vec<LookupRecord> = tools
+                            .iter()
+                            .filter_map(|spec| Some(LookupRecord::new(spec, product.find_release(spec, &registry).err()?)))
+                            .collect();
+                        print_json(&records)?;
+                    }
+                    return Err(e.into());
+                }
+            };
+            install_tools(selected_tools, &taps, &config, options, &mut registry).await?;
         }
+        Some(Commands::Update { tools, all, from_source }) => {
+            let selected_tools = if all {
+                let (pinned, unpinned): (Vec<&Tool>, Vec<&Tool>) = product
+                    .installed_tools(&registry)
+                    .into_iter()
+                    .partition(|tool| registry.get(&tool.name).is_some_and(|installed| installed.pinned));
+                for tool in pinned {
+                    println!("{} is pinned, skipping it.", tool.name);
+                }
+                unpinned.into_iter().map(|tool| (tool, tool.latest())).collect()
+            } else {
+                product.find_tools(&tools, &registry)?
+            };
 
-        // if installed_version > latest_version {
-        //     return format!("(Latest installed {})", latest_version);
-        // }
+            for (tool, _) in &selected_tools {
+                if !tool.is_installed(&registry) {
+                    let message = format!("{} is not installed, use 'wrap install {}' instead.", tool.name, tool.name);
+                    return Err(WrapError::Usage(message).into());
+                }
+            }
+
+            let options = InstallOptions { from_source: from_source || defaults.from_source, ..defaults };
+            install_tools(selected_tools, &taps, &config, options, &mut registry).await?;
+        }
+        Some(Commands::List { json }) => {
+            let mut records = Vec::new();
+            for tool in &product.tools {
+                let shadowed_by = match product.find_tool(&tool.name, &registry) {
+                    Some(preferred) if !std::ptr::eq(preferred, tool) => Some(preferred),
+                    _ => None,
+                };
+                if json {
+                    let shadowed_by = shadowed_by.map(|preferred| preferred.registry.clone());
+                    records.push(ToolRecord { shadowed_by, ..ToolRecord::new(tool.state(&registry)) });
+                    continue;
+                }
 
-        return "".to_string();
+                let description = match shadowed_by {
+                    Some(preferred) => {
+                        format!("(shadowed by {}, use {}/{})", preferred.registry, tool.registry, tool.name)
+                    }
+                    None => tool.install_description(&registry),
+                };
+                println!("{} {} [{}] {}", tool.name, tool.latest().version, tool.registry, description);
+            }
+            if json {
+                print_json(&records)?;
+            }
+        }
+        Some(Commands::Outdated { json }) => {
+            let outdated_tools = product.outdated_tools(&registry);
+            if json {
+                let records: Vec<ToolRecord> =
+                    outdated_tools.iter().map(|tool| ToolRecord::new(tool.state(&registry))).collect();
+                return print_json(&records);
+            }
+            if outdated_tools.is_empty() {
+                println!("All installed tools are up to date.");
+            }
+            for tool in outdated_tools {
+                println!("{} {}", tool.name, tool.install_description(&registry));
+            }
+        }
+        Some(Commands::SelfUpdate { from_source }) => {
+            let options = InstallOptions { from_source: from_source || defaults.from_source, ..defaults };
+            self_update(&product, &config, options).await?;
+        }
+        Some(Commands::Uninstall { .. })
+        | Some(Commands::Rollback { .. })
+        | Some(Commands::Pin { .. })
+        | Some(Commands::Unpin { .. })
+        | Some(Commands::Cache { .. })
+        | Some(Commands::Clean)
+        | Some(Commands::Doctor)
+        | Some(Commands::Bootstrap { .. })
+        | Some(Commands::Config { .. })
+        | Some(Commands::Manifest { .. }) => {
+            unreachable!("handled before loading the manifest")
+        }
     }
+
+    Ok(())
 }
 
-impl Product {
-    pub fn filter_tools_by_user(&self) -> Vec<&Tool> {
-        // Print the list of tools with their indices
-        println!("Select one or more programs by their number (separated by space):");
-        for (i, tool) in self.tools.iter().enumerate() {
-            println!("{}) {} {}", i + 1, tool.name, tool.install_description());
+fn run_manifest_command(command: ManifestCommands) -> Result<(), Box<dyn Error>> {
+    match command {
+        ManifestCommands::Keygen { key } => {
+            let key = key.unwrap_or_else(signature::default_signing_key_path);
+            let public_key = signature::generate_key(&key)?;
+            println!("Private key written to {}", key.display());
+            println!("Public key: {}", public_key);
+            println!("Add it as public-key in {} to trust manifests signed with it.", Config::path().display());
+        }
+        ManifestCommands::Sign { file, key } => {
+            let key = key.unwrap_or_else(signature::default_signing_key_path);
+            let signature = signature::sign_file(&file, &key)?;
+            println!("Signature written to {}", signature.display());
         }
+    }
 
-        let mut selected_indices: Vec<usize>;
-        loop {
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            selected_indices = input
-                .trim()
-                .split(' ')
-                .map(|x| x.parse::<usize>().unwrap_or(usize::MAX))
-                .filter(|&x| x > 0 && x - 1 < self.tools.len())
-                .collect();
-            if !selected_indices.is_empty() {
-                break;
+    Ok(())
+}
+
+fn print_config(config: &Config, key: Option<&str>) -> Result<(), Box<dyn Error>> {
+    match key {
+        Some(key) => println!("{}", config.get(key)?),
+        None => {
+            for key in config::KEYS {
+                println!("{} = {}", key, config.get(key)?);
             }
-            println!("Invalid input. Try again:");
         }
-        // println!("selected_indices {:#?}", selected_indices); //debug
+    }
 
-        // Filter tools by index
-        let filtered_tools = self
-            .tools
-            .iter()
-            .enumerate()
-            .filter(|(i, _)| selected_indices.contains(&(i + 1)))
-            .map(|(_, tool)| tool)
-            .collect::<Vec<_>>();
+    Ok(())
+}
+
+fn set_config(key: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+    Config::set(key, value)?;
 
-        filtered_tools
+    let path = Config::path();
+    match value {
+        Some(value) => println!("Set {} to {} in {}", key, value, path.display()),
+        None => println!("Removed {} from {}", key, path.display()),
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<(), reqwest::Error> {
-    let product: Product = Client::new()
-        .get("https://raw.githubusercontent.com/wormaga/wrap-solution/main/wrap.json")
-        .send()
-        .await?
-        .json()
-        .await?;
-    //println!("{:#?}", product); //debug
+    // the environment wins over the file
+    if env::var_os(config::env_var(key)).is_some_and(|value| !value.is_empty()) {
+        eprintln!("Warning: {} is set and overrides this setting.", config::env_var(key));
+    }
 
-    let selected_tools = product.filter_tools_by_user();
+    Ok(())
+}
 
-    if selected_tools.is_empty() {
-        println!("No tools were selected. Exiting the program.");
-        process::exit(0);
+fn run_cache_command(command: CacheCommands) -> Result<(), Box<dyn Error>> {
+    match command {
+        CacheCommands::List => {
+            let downloads = cache::downloads();
+            if downloads.is_empty() {
+                println!("The download cache is empty.");
+                return Ok(());
+            }
+
+            for download in &downloads {
+                println!(
+                    "{} {} ({} ago)",
+                    download.url,
+                    cache::format_size(download.size),
+                    cache::format_age(Utc::now() - download.cached_at)
+                );
+            }
+            let total = downloads.iter().map(|download| download.size).sum();
+            println!("Total {} in {}", cache::format_size(total), cache::downloads_dir().display());
+        }
+        CacheCommands::Clean => {
+            let freed = cache::clean_downloads()?;
+            println!("Download cache cleaned, {} freed.", cache::format_size(freed));
+        }
     }
 
-    for tool in selected_tools {
-        if tool.is_update_available() {
+    Ok(())
+}
+
+/// Installs the tools one by one. A tool that fails is reported and skipped, the rest are still installed.
+async fn install_tools(
+    tools: Vec<(&Tool, &Release)>,
+    taps: &[Tap],
+    config: &Config,
+    options: InstallOptions,
+    registry: &mut Registry,
+) -> Result<(), Box<dyn Error>> {
+    let downloader = Downloader::new(config, options.offline)?;
+    let mut results = Vec::new();
+
+    // every tool succeeds or fails on its own
+    for (tool, release) in tools {
+        let outcome = if tool.needs_install(release, registry) {
+            if registry.get(&tool.name).is_none() && tool.is_installed(registry) {
+                progress!("{} is not in the install registry yet, installing it again to record it.", tool.name);
+            }
+            if let Some(other) = tool.installed_from_other_registry(registry) {
+                progress!("{} is installed from {}, replacing it with {}/{}.", tool.name, other, tool.registry, tool.name);
+            }
             //println!("Debug: update is available"); //debug
-            install_tool(&tool).await
+            match install_tool(tool, release, taps, config, options, &downloader, registry).await {
+                Ok(()) => Outcome::Installed,
+                Err(e) => {
+                    eprintln!("Failed to install {}: {}", tool.name, e);
+                    if registry.get(&tool.name).is_some() || tool.is_installed(registry) {
+                        eprintln!("The previous installation of {} was left untouched.", tool.name);
+                    }
+                    Outcome::Failed(WrapError::classify(e, WrapError::Install))
+                }
+            }
         } else {
-            println!("The latest version is installed.");
-        }
+            if std::ptr::eq(release, tool.latest()) {
+                progress!("The latest version of {} is installed.", tool.name);
+            } else {
+                progress!("{} {} is already installed.", tool.name, release.version);
+            }
+            Outcome::AlreadyInstalled
+        };
+
+        results.push(InstallResult { version: release.version.clone(), state: tool.state(registry), outcome });
+    }
+
+    if output::is_json() {
+        let records: Vec<ToolRecord> = results.iter().map(InstallResult::record).collect();
+        print_json(&records)?;
+    } else if results.len() > 1 {
+        print_summary(&results);
+    }
+
+    let failures: Vec<(String, WrapError)> = results
+        .into_iter()
+        .filter_map(|result| match result.outcome {
+            Outcome::Failed(e) => Some((result.state.tool, e)),
+            _ => None,
+        })
+        .collect();
+    if !failures.is_empty() {
+        return Err(WrapError::Tools(failures).into());
     }
 
     Ok(())
 }
 
-async fn install_tool(tool: &Tool) {
-    //println!("{:#?}", tool); //debug
+/// What `install_tools` did with one tool.
+struct InstallResult {
+    version: String,
+    /// The tool after the install
+    state: ToolState,
+    outcome: Outcome,
+}
+
+impl InstallResult {
+    fn record(&self) -> ToolRecord {
+        let (result, errors) = match &self.outcome {
+            Outcome::Installed => ("installed", Vec::new()),
+            Outcome::AlreadyInstalled => ("already-installed", Vec::new()),
+            Outcome::Failed(e) => ("failed", vec![ErrorRecord { kind: e.kind(), message: e.to_string() }]),
+        };
+
+        ToolRecord { result: Some(result), errors, ..ToolRecord::new(self.state.clone()) }
+    }
+}
 
-    ensure_rust_up_to_date();
+enum Outcome {
+    Installed,
+    AlreadyInstalled,
+    Failed(WrapError),
+}
+
+/// One tool in the output of `--json`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolRecord {
+    #[serde(flatten)]
+    state: ToolState,
+    /// Registry of the tool with the same name that a plain name refers to
+    shadowed_by: Option<String>,
+    /// What install did: installed, already-installed or failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'static str>,
+    errors: Vec<ErrorRecord>,
+}
+
+impl ToolRecord {
+    fn new(state: ToolState) -> ToolRecord {
+        ToolRecord { state, shadowed_by: None, result: None, errors: Vec::new() }
+    }
+}
+
+/// A `<tool>` or `<tool>@<version>` given to `install --json` that could not be looked up.
+#[derive(Serialize)]
+struct LookupRecord {
+    tool: String,
+    result: &'static str,
+    errors: Vec<ErrorRecord>,
+}
+
+impl LookupRecord {
+    fn new(spec: &str, error: WrapError) -> LookupRecord {
+        let tool = spec.split_once('@').map_or(spec, |(name, _)| name);
+        let errors = vec![ErrorRecord { kind: error.kind(), message: error.to_string() }];
+        LookupRecord { tool: tool.to_string(), result: "failed", errors }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorRecord {
+    kind: &'static str,
+    message: String,
+}
+
+fn print_json<T: Serialize>(records: &[T]) -> Result<(), Box<dyn Error>> {
+    let mut stdout = io::stdout().lock();
+    let written = serde_json::to_writer_pretty(&mut stdout, records)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(stdout))
+        .and_then(|()| stdout.flush());
+
+    match written {
+        // the reader went away, e.g. `wrap list --json | head -1`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => Ok(written?),
+    }
+}
+
+/// A table with one line per tool, printed after installing several.
+fn print_summary(results: &[InstallResult]) {
+    let name_width = results.iter().map(|result| result.state.tool.len()).chain(["TOOL".len()]).max().unwrap_or(0);
+    let version_width = results.iter().map(|result| result.version.len()).chain(["VERSION".len()]).max().unwrap_or(0);
+
+    println!();
+    println!("{:<name_width$}  {:<version_width$}  RESULT", "TOOL", "VERSION");
+    for result in results {
+        let outcome = match &result.outcome {
+            Outcome::Installed => "installed".to_string(),
+            Outcome::AlreadyInstalled => "already installed".to_string(),
+            Outcome::Failed(e) => format!("failed ({})", e.kind()),
+        };
+        println!("{:<name_width$}  {:<version_width$}  {}", result.state.tool, result.version, outcome);
+    }
+}
+
+/// Downloads and builds the tool in a staging directory. The installed binary and project
+/// folder are only replaced once the build succeeded. Offline, a version kept for rollback is
+/// reinstalled instead.
+async fn install_tool(
+    tool: &Tool,
+    release: &Release,
+    taps: &[Tap],
+    config: &Config,
+    options: InstallOptions,
+    downloader: &Downloader,
+    registry: &mut Registry,
+) -> Result<(), Box<dyn Error>> {
+    //println!("{:#?}", tool); //debug
 
     let project_name = &tool.name;
+    let version = Version::parse(&release.version).map_err(|e| {
+        WrapError::VersionDetection(format!("invalid version {} of {} in the manifest: {}", release.version, project_name, e))
+    })?;
 
-    let home_dir = dirs::home_dir().expect("failed to get home directory");
-    let bin_dir = home_dir.join(BIN);
-    let cli_projects_dir = home_dir.join(CLI_PROJECTS);
+    let bin_dir = bin_dir();
+    fs::create_dir_all(&bin_dir)?;
 
-    set_current_directory(&bin_dir);
-    set_current_directory(&cli_projects_dir);
+    let kept = store::binary_path(project_name, &version);
+    let stored = if options.offline && kept.exists() {
+        progress!("Reinstalling {} {} from the kept versions", project_name, version);
+        kept
+    } else {
+        build_and_store(project_name, release, &version, options, downloader).await?
+    };
 
-    //deleting existing project code
-    let tool_dir = cli_projects_dir.join(&project_name);
-    if tool_dir.exists() {
-        delete_folder(&tool_dir).expect("failed to delete litegallery directory");
-        println!("Deleted existing {} project folder.", &project_name);
+    //move compiled program to ${HOME}/bin folder
+    progress!("Coping program {} to {}", project_name, bin_dir.display());
+    let binary = bin_dir.join(project_name);
+    store::activate(&stored, &binary)?;
+
+    let tap = taps
+        .iter()
+        .find(|tap| tap.name == tool.registry)
+        .ok_or_else(|| format!("unknown registry {}", tool.registry))?;
+    let pinned = registry.get(project_name).is_some_and(|installed| installed.pinned);
+    registry.insert(InstalledTool {
+        name: tool.name.clone(),
+        version: release.version.clone(),
+        installed_at: Utc::now(),
+        binary_hash: registry::hash_file(&binary)?,
+        binary,
+        source: tap.source()?.canonical().to_string(),
+        registry: Some(tap.name.clone()),
+        pinned,
+    });
+    registry.save()?;
+
+    store::prune(project_name, config.keep_versions(), &version)?;
+
+    progress!("{} is installed.", project_name);
+
+    Ok(())
+}
+
+/// Gets the release as a prebuilt binary or by building it in a staging directory, keeps a copy for
+/// rollbacks and swaps in the new project folder. Returns the kept binary.
+async fn build_and_store(
+    project_name: &str,
+    release: &Release,
+    version: &Version,
+    options: InstallOptions,
+    downloader: &Downloader,
+) -> Result<PathBuf, Box<dyn Error>> {
+
+    // a prebuilt binary for this platform needs neither rust nor a build
+    let prebuilt = if options.from_source { None } else { release.binary_for(source::HOST_TARGET) };
+    if prebuilt.is_none() && !options.from_source && !release.binaries.is_empty() {
+        progress!("No prebuilt {} for {}, building from source", project_name, source::HOST_TARGET);
     }
 
-    // create a new rust project
-    println!("Creating a new rust project");
-    let output = Command::new("cargo")
-        .arg("new")
-        .arg(&project_name)
-        .output()
-        .expect("failed create a new cargo project.");
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    let cli_projects_dir = cli_projects_dir();
+    fs::create_dir_all(&cli_projects_dir)?;
 
-    //go inside the project
-    set_current_directory(&tool_dir);
+    // leftovers of an interrupted install are never reused
+    let staging_dir = cli_projects_dir.join(format!(".{}.staging", project_name));
+    if staging_dir.exists() {
+        delete_folder(&staging_dir)?;
+    }
+
+    let tool_dir = cli_projects_dir.join(project_name);
+    let result = match prebuilt {
+        Some(binary) => source::fetch_binary(project_name, binary, &staging_dir, downloader).await,
+        None => build_in_staging(project_name, release, &staging_dir, &tool_dir, options, downloader).await,
+    };
+    if result.is_err() && staging_dir.exists() {
+        delete_folder(&staging_dir)?;
+    }
+    let built_binary = result?;
 
-    println!("Downloading up to date files from github");
-    for asset in &tool.files {
-        set_current_directory(&(tool_dir.join(&asset.location)));
+    //keep a copy for rollbacks
+    let stored = store::add(project_name, version, &built_binary)?;
 
-        download_file(&asset.url, &asset.filename)
-            .await
-            .expect("failed to download file.");
+    // swap the project folder for the freshly built one. A prebuilt install has none and keeps the
+    // old one, so that a later build from source can still reuse it.
+    if prebuilt.is_some() {
+        delete_folder(&staging_dir)?;
+    } else {
+        if tool_dir.exists() {
+            delete_folder(&tool_dir)?;
+        }
+        fs::rename(&staging_dir, &tool_dir)?;
     }
-    println!("All files downlaoded.");
 
-    //go inside the project
-    set_current_directory(&tool_dir);
+    Ok(stored)
+}
+
+/// Creates the project in `staging_dir`, downloads its files and builds it. Returns the built binary.
+/// The Cargo.lock of the previous version in `tool_dir` is reused, so the compiled dependencies in the
+/// shared target directory carry over to the new version.
+async fn build_in_staging(
+    project_name: &str,
+    release: &Release,
+    staging_dir: &Path,
+    tool_dir: &Path,
+    options: InstallOptions,
+    downloader: &Downloader,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let toolchain = toolchain::prepare(release, options.toolchain_update && !options.offline)
+        .map_err(|e| WrapError::Build(e.to_string()))?;
 
-    let output = Command::new("pwd")
-        .output()
-        .expect("failed compile a project.");
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    match release.source()? {
+        ToolSource::Files(files) => source::fetch_files(project_name, files, staging_dir, downloader).await?,
+        ToolSource::Archive(archive) => source::fetch_archive(archive, staging_dir, downloader).await?,
+        ToolSource::Git(git) => source::fetch_git(git, staging_dir)?,
+    }
+
+    let lock_file = tool_dir.join("Cargo.lock");
+    if lock_file.exists() && !staging_dir.join("Cargo.lock").exists() {
+        fs::copy(&lock_file, staging_dir.join("Cargo.lock"))?;
+    }
 
-    println!("Compiling program {}", &project_name);
-    let output = Command::new("cargo")
-        .arg("build")
-        .arg("--release")
+    // never pick up the binary of an earlier build
+    let target_dir = target_dir();
+    let binary = target_dir.join("release").join(project_name);
+    if binary.exists() {
+        fs::remove_file(&binary)?;
+    }
+
+    let mut command = Command::new("cargo");
+    match &toolchain {
+        Some(toolchain) => {
+            progress!("Compiling program {} with toolchain {}", project_name, toolchain);
+            command.arg(format!("+{}", toolchain));
+        }
+        None => progress!("Compiling program {}", project_name),
+    }
+    command.arg("build").arg("--release");
+    if let Some(key) = signature::builtin_public_key().filter(|_| project_name == SELF_NAME) {
+        command.env("WRAP_BUILTIN_PUBLIC_KEY", key);
+    }
+    if options.offline {
+        command.arg("--offline");
+    }
+    let output = command
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .current_dir(staging_dir)
         .output()
-        .expect("failed compile a project.");
+        .map_err(|e| WrapError::Build(format!("failed to run cargo: {}", e)))?;
 
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    progress!("{}", String::from_utf8_lossy(&output.stdout));
     if !output.status.success() {
         let error_message = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Cargo build failed: {}", error_message);
-        std::process::exit(1);
+        return Err(WrapError::Build(format!("cargo build failed: {}", error_message)).into());
     }
 
-    //move compiled program to ${HOME}/bin folder
-    println!("Coping program {} to ~/bin folder", &project_name);
-    let output = Command::new("cp")
-        .arg(&format!("target/release/{}", &project_name))
-        .arg(&bin_dir)
-        .output()
-        .expect("failed compile a project.");
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    // archive and git sources bring their own Cargo.toml, the package has to be named like the tool
+    if !binary.exists() {
+        return Err(WrapError::Build(format!("cargo build did not produce a binary named {}", project_name)).into());
+    }
 
-    println!("{} is installed.", &project_name);
+    Ok(binary)
 }
 
-fn set_current_directory(dir: &Path) {
-    // Create the folder if it does not exist
-    if !dir.exists() {
-        fs::create_dir(&dir).expect("failed to create directory");
+/// Replaces the running wrap with the release in the default manifest, built or downloaded like any
+/// other tool. The project folder and kept versions live under the name wrap.
+async fn self_update(product: &Product, config: &Config, options: InstallOptions) -> Result<(), Box<dyn Error>> {
+    let release = product.wrap.as_ref().ok_or("the default manifest has no wrap entry to update from")?;
+    let current = Version::parse(env!("CARGO_PKG_VERSION"))?;
+    let latest = release
+        .parsed_version()
+        .ok_or_else(|| WrapError::VersionDetection(format!("invalid wrap version {} in the manifest", release.version)))?;
+
+    if latest <= current {
+        println!("wrap {} is up to date.", current);
+        return Ok(());
+    }
+
+    // a tag can be moved, only a commit pins what replaces the running wrap
+    if let Ok(ToolSource::Git(git)) = release.source() {
+        if git.commit.is_none() {
+            let message = format!("the wrap entry's git source {} has no commit, not updating to an unchecked build", git.url);
+            return Err(WrapError::Checksum(message).into());
+        }
     }
 
-    // Change the current directory
-    env::set_current_dir(&dir).expect("failed to change directory");
+    let executable = env::current_exe().map_err(|e| format!("cannot find the wrap executable: {}", e))?;
+    println!("Updating wrap {} to {}", current, latest);
+
+    let downloader = Downloader::new(config, options.offline)?;
+    let stored = build_and_store(SELF_NAME, release, &latest, options, &downloader).await?;
+
+    // renaming over the running executable is safe, the running process keeps the old file
+    store::activate(&stored, &executable).map_err(|e| WrapError::Install(e.to_string()))?;
+    store::prune(SELF_NAME, config.keep_versions(), &latest)?;
+
+    println!("wrap is updated to {} ({}).", latest, executable.display());
+
+    Ok(())
 }
 
-fn delete_folder(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    // Delete the folder
-    fs::remove_dir_all(dir)?;
+/// Tells the user on stderr when the default manifest has a newer wrap.
+fn notify_self_update(product: &Product) {
+    let latest = product.wrap.as_ref().and_then(|release| release.parsed_version());
+    let current = Version::parse(env!("CARGO_PKG_VERSION")).ok();
+    if let (Some(latest), Some(current)) = (latest, current) {
+        if latest > current {
+            eprintln!("A newer wrap {} is available (you have {}), run 'wrap self-update'.", latest, current);
+        }
+    }
+}
+
+fn uninstall_tool(name: &str, yes: bool) -> Result<(), Box<dyn Error>> {
+    let mut registry = Registry::load()?;
+
+    // only ever delete what wrap put there itself
+    let installed = match registry.get(name) {
+        Some(installed) => installed.clone(),
+        None => {
+            if bin_dir().join(name).exists() {
+                return Err(WrapError::Refused(format!(
+                    "{} was not installed by wrap, leaving it alone. If an older wrap installed it, run 'wrap install {}' first.",
+                    bin_dir().join(name).display(),
+                    name
+                ))
+                .into());
+            }
+            return Err(WrapError::Usage(format!("{} is not installed.", name)).into());
+        }
+    };
+
+    if installed.binary.exists() && registry::hash_file(&installed.binary)? != installed.binary_hash {
+        return Err(WrapError::Refused(format!(
+            "{} was changed after wrap installed it, leaving it alone. Remove it by hand if you no longer need it.",
+            installed.binary.display()
+        ))
+        .into());
+    }
+
+    let tool_dir = cli_projects_dir().join(name);
+
+    if !yes {
+        println!("This will delete:");
+        if installed.binary.exists() {
+            println!("  {}", installed.binary.display());
+        }
+        if tool_dir.exists() {
+            println!("  {}", tool_dir.display());
+        }
+        if !confirm(&format!("Uninstall {} {}?", name, installed.version)) {
+            println!("Nothing was removed.");
+            return Ok(());
+        }
+    }
+
+    if installed.binary.exists() {
+        fs::remove_file(&installed.binary)?;
+        println!("Deleted {}", installed.binary.display());
+    }
+
+    if tool_dir.exists() {
+        delete_folder(&tool_dir)?;
+        println!("Deleted {}", tool_dir.display());
+    }
+
+    store::remove(name)?;
+    registry.remove(name);
+    registry.save()?;
+
+    println!("{} is uninstalled.", name);
 
     Ok(())
 }
 
-async fn download_file(url: &str, filename: &str) -> Result<(), Error> {
-    let resp = reqwest::get(url).await.expect("request failed");
-    assert!(resp.status().is_success());
-    let body = resp.text().await.expect("body invalid");
-    let mut out = File::create(filename).expect("failed to create file");
-    io::copy(&mut body.as_bytes(), &mut out).expect("failed to copy content");
+fn rollback_tool(name: &str, version: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut registry = Registry::load()?;
+    let mut installed = registry
+        .get(name)
+        .cloned()
+        .ok_or_else(|| WrapError::Usage(format!("{} is not installed by wrap.", name)))?;
+    let current = Version::parse(&installed.version)
+        .map_err(|e| WrapError::VersionDetection(format!("invalid installed version {}: {}", installed.version, e)))?;
+    let versions = store::versions(name);
+
+    let target = match version {
+        Some(version) => {
+            let version = Version::parse(version.trim_start_matches('v'))
+                .map_err(|e| WrapError::Usage(format!("invalid version {}: {}", version, e)))?;
+            if !versions.contains(&version) {
+                let message = format!("{} {} is not stored, available: {}", name, version, format_versions(&versions));
+                return Err(WrapError::Usage(message).into());
+            }
+            version
+        }
+        None => versions
+            .iter()
+            .find(|version| **version < current)
+            .cloned()
+            .ok_or_else(|| WrapError::Usage(format!("no version of {} older than {} is stored.", name, current)))?,
+    };
+
+    if target == current {
+        println!("{} {} is already active.", name, current);
+        return Ok(());
+    }
+
+    store::activate(&store::binary_path(name, &target), &installed.binary)?;
+
+    installed.version = target.to_string();
+    installed.installed_at = Utc::now();
+    installed.binary_hash = registry::hash_file(&installed.binary)?;
+    registry.insert(installed);
+    registry.save()?;
+
+    println!("{} rolled back from {} to {}.", name, current, target);
 
     Ok(())
 }
 
-fn ensure_rust_up_to_date() {
-    use std::process::Command;
+fn pin_tool(name: &str, pinned: bool) -> Result<(), Box<dyn Error>> {
+    let mut registry = Registry::load()?;
+    let mut installed = registry
+        .get(name)
+        .cloned()
+        .ok_or_else(|| WrapError::Usage(format!("{} is not installed by wrap.", name)))?;
 
-    // Check rustc version
-    let output = Command::new("rustc")
-        .arg("--version")
-        .output()
-        .expect("Failed to run rustc");
+    if installed.pinned == pinned {
+        println!("{} is already {}.", name, if pinned { "pinned" } else { "not pinned" });
+        return Ok(());
+    }
+
+    installed.pinned = pinned;
+    let version = installed.version.clone();
+    registry.insert(installed);
+    registry.save()?;
+
+    if pinned {
+        println!("{} is pinned at {}, 'wrap update --all' will skip it.", name, version);
+    } else {
+        println!("{} is no longer pinned.", name);
+    }
+
+    Ok(())
+}
+
+/// Removes the shared target directory, `target` folders left in project trees by older versions
+/// of wrap and leftovers of interrupted installs. Installed tools and project sources are kept.
+fn clean_build_output() -> Result<(), Box<dyn Error>> {
+    let mut dirs = vec![target_dir()];
+    if let Ok(entries) = fs::read_dir(cli_projects_dir()) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') && (name.ends_with(".staging") || name.ends_with(".download")) {
+                dirs.push(path);
+            } else if path.join("target").is_dir() {
+                dirs.push(path.join("target"));
+            }
+        }
+    }
+
+    let mut freed = 0;
+    for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+        freed += dir_size(dir);
+        delete_folder(dir)?;
+        println!("Deleted {}", dir.display());
+    }
+
+    println!("{} freed.", cache::format_size(freed));
+
+    Ok(())
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        })
+        .sum()
+}
+
+fn format_versions(versions: &[Version]) -> String {
+    if versions.is_empty() {
+        return "none".to_string();
+    }
 
-    let version_str = String::from_utf8_lossy(&output.stdout);
-    println!("Current Rust version: {}", version_str.trim());
+    versions.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+}
 
-    // Optionally parse the version number and compare with a minimum
-    // For simplicity, just update Rust every time
-    println!("Updating Rust toolchain...");
-    let status = Command::new("rustup")
-        .arg("update")
-        .arg("stable")
-        .status()
-        .expect("Failed to update Rust via rustup");
+/// Asks a yes/no question on stdin, defaulting to no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
 
-    if !status.success() {
-        eprintln!("Rust update failed. Please update manually.");
-        std::process::exit(1);
+    // no answer counts as no
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return false;
     }
 
-    println!("Rust is up to date!");
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn delete_folder(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // Delete the folder
+    fs::remove_dir_all(dir)?;
+
+    Ok(())
 }
@@ -0,0 +1,808 @@
+use ed25519_dalek::VerifyingKey;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cache::{self, Fetched, ManifestClient};
+use crate::config::bin_dir;
+use crate::error::WrapError;
+use crate::registry::Registry;
+use crate::signature::{self, SIGNATURE_EXTENSION};
+use crate::tap::DEFAULT_TAP;
+use crate::version;
+
+pub static DEFAULT_MANIFEST: &str = "https://raw.githubusercontent.com/wormaga/wrap-solution/main/wrap.json";
+static MANIFEST_FILENAME: &str = "wrap.json";
+
+/// Where the manifest is loaded from: a remote URL or a local file.
+#[derive(Debug, Clone)]
+pub enum ManifestSource {
+    Url(Url),
+    File(PathBuf),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Product {
+    pub last_update: String,
+    pub tools: Vec<Tool>,
+    /// The release of wrap itself, used by `wrap self-update`. Only read from the default registry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrap: Option<Release>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    pub name: String,
+    /// Name of the registry the tool was loaded from, set after loading
+    #[serde(skip)]
+    pub registry: String,
+    /// The release described at the top level of the entry
+    #[serde(flatten)]
+    pub release: Release,
+    /// More releases, installable with `<tool>@<version>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub releases: Vec<Release>,
+    /// How to find the version of a copy wrap did not install itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_check: Option<VersionCheck>,
+}
+
+/// Runs a command and extracts the installed version from its output.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionCheck {
+    /// Program to run, ~/bin/<tool> by default. It has to be in ~/bin, a manifest cannot make wrap
+    /// run anything else.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Arguments, ["--version"] by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+    /// Regex matching the version; its first capture group is used if it has one. By default the
+    /// first word that is a semver is taken.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+}
+
+/// What wrap knows about the installed copy of a tool.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Installed {
+    No,
+    /// A binary is there, but its version could not be determined
+    UnknownVersion,
+    Version(Version),
+}
+
+/// How the installed copy of a tool compares with its latest release.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    NotInstalled,
+    /// A binary is there, but its version could not be determined
+    UnknownVersion,
+    UpToDate,
+    UpdateAvailable,
+    /// Installed, but the latest version is no semver or older than the installed one
+    Installed,
+}
+
+/// What is known about a tool, here and in the manifest. Serialized for `--json`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolState {
+    pub tool: String,
+    pub registry: String,
+    pub installed_version: Option<String>,
+    pub latest_version: String,
+    pub status: Status,
+    pub pinned: bool,
+    /// The installed binary, if there is one
+    pub binary: Option<PathBuf>,
+}
+
+/// One version of a tool and where to get it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Release {
+    pub version: String,
+    /// Oldest rustc that can build this release, e.g. "1.70"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
+    /// Toolchain to build with (`cargo +<toolchain>`), e.g. "nightly" or "1.75.0"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// Individual files making up the project
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<Asset>,
+    /// A tar.gz or zip with the whole project, used instead of `files`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<Archive>,
+    /// A git repository with the project, used instead of `files`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitSource>,
+    /// Prebuilt executables keyed by target triple, installed instead of building when one matches
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub binaries: BTreeMap<String, Binary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Asset {
+    pub location: String,
+    pub filename: String,
+    pub url: String,
+    #[serde(flatten)]
+    pub checksums: Checksums,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Archive {
+    /// URL of a .tar.gz/.tgz or .zip file
+    pub url: String,
+    /// Directory inside the archive holding Cargo.toml. A single top-level directory, as in
+    /// GitHub archives, is stripped first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub checksums: Checksums,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitSource {
+    /// Anything `git clone` accepts
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Commit to check out, or to check the tag against when both are given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Directory inside the repository holding Cargo.toml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Binary {
+    /// URL of the executable, or of a .tar.gz/.tgz/.zip containing it
+    pub url: String,
+    /// Path of the executable inside the archive, the tool name by default. A single top-level
+    /// directory is stripped first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub checksums: Checksums,
+}
+
+/// Where the source code of a tool comes from.
+pub enum ToolSource<'a> {
+    Files(&'a [Asset]),
+    Archive(&'a Archive),
+    Git(&'a GitSource),
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checksums {
+    /// Expected hex encoded SHA-256 of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Expected hex encoded BLAKE3 hash of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blake3: Option<String>,
+}
+
+impl Checksums {
+    pub fn is_empty(&self) -> bool {
+        self.sha256.is_none() && self.blake3.is_none()
+    }
+
+    /// Checks a downloaded file against the checksums listed in the manifest. `name` is used in errors.
+    pub fn verify(&self, path: &Path, name: &str) -> Result<(), String> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let content = fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+        if let Some(expected) = &self.sha256 {
+            let actual = format!("{:x}", Sha256::digest(&content));
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                return Err(format!("sha256 mismatch for {}: expected {}, got {}", name, expected, actual));
+            }
+        }
+
+        if let Some(expected) = &self.blake3 {
+            let actual = blake3::hash(&content).to_hex().to_string();
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                return Err(format!("blake3 mismatch for {}: expected {}, got {}", name, expected, actual));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Release {
+    /// The one source the release names: `files`, `archive` or `git`.
+    pub fn source(&self) -> Result<ToolSource<'_>, String> {
+        match (&self.archive, &self.git) {
+            (None, None) if !self.files.is_empty() => Ok(ToolSource::Files(&self.files)),
+            (Some(archive), None) if self.files.is_empty() => Ok(ToolSource::Archive(archive)),
+            (None, Some(git)) if self.files.is_empty() => Ok(ToolSource::Git(git)),
+            (None, None) => Err(format!("release {} has no files, archive or git source in the manifest", self.version)),
+            _ => Err(format!("release {} must use only one of files, archive or git in the manifest", self.version)),
+        }
+    }
+
+    /// The release version, None if the manifest has something that is not a semver.
+    pub fn parsed_version(&self) -> Option<Version> {
+        version::parse(&self.version, None)
+    }
+
+    /// The prebuilt binary for the given target triple, if the manifest lists one.
+    pub fn binary_for(&self, target: &str) -> Option<&Binary> {
+        self.binaries.get(target)
+    }
+}
+
+impl Tool {
+    /// All releases listed for the tool, in manifest order.
+    pub fn releases(&self) -> impl Iterator<Item = &Release> {
+        std::iter::once(&self.release).chain(&self.releases)
+    }
+
+    /// The newest release, installed when no version is asked for.
+    pub fn latest(&self) -> &Release {
+        self.releases()
+            .max_by_key(|release| release.parsed_version())
+            .unwrap_or(&self.release)
+    }
+
+    /// The newest release matching the requirement.
+    pub fn find_release(&self, requirement: &VersionReq) -> Option<&Release> {
+        self.releases()
+            .filter_map(|release| Some((release.parsed_version()?, release)))
+            .filter(|(version, _)| requirement.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, release)| release)
+    }
+
+    pub fn is_installed(&self, registry: &Registry) -> bool {
+        self.installed(registry) != Installed::No
+    }
+
+    /// The registry the installed copy came from, if that is not the one of this tool, e.g. `default`
+    /// for `team/hello` while `default/hello` is installed. Entries older than registries came from `default`.
+    pub fn installed_from_other_registry<'a>(&self, registry: &'a Registry) -> Option<&'a str> {
+        let installed = registry.get(&self.name)?;
+        let installed_from = installed.registry.as_deref().unwrap_or(DEFAULT_TAP);
+        (installed_from != self.registry).then_some(installed_from)
+    }
+
+    /// The installed version according to the registry. Tools installed without it are probed
+    /// with their `versionCheck`, by default `~/bin/<name> --version`. A copy installed from another
+    /// registry does not count as this tool.
+    pub fn installed(&self, registry: &Registry) -> Installed {
+        if self.installed_from_other_registry(registry).is_some() {
+            return Installed::No;
+        }
+        if let Some(installed) = registry.get(&self.name) {
+            return match version::parse(&installed.version, None) {
+                Some(version) => Installed::Version(version),
+                None => Installed::UnknownVersion,
+            };
+        }
+
+        let binary = bin_dir().join(&self.name);
+        if !binary.exists() {
+            return Installed::No;
+        }
+
+        let check = self.version_check.as_ref();
+        let program = match check.and_then(|check| check.command.as_deref()) {
+            Some(command) => match program_in_bin_dir(command) {
+                Some(program) => program,
+                None => {
+                    eprintln!(
+                        "Warning: not running the version check {} of {}, it is not a program in {}.",
+                        command,
+                        self.name,
+                        bin_dir().display()
+                    );
+                    return Installed::UnknownVersion;
+                }
+            },
+            None => binary,
+        };
+        let default_args = vec!["--version".to_string()];
+        let args = check.and_then(|check| check.args.as_ref()).unwrap_or(&default_args);
+        let regex = check.and_then(|check| check.regex.as_deref());
+
+        match version::detect(&program, args, regex) {
+            Some(version) => Installed::Version(version),
+            None => Installed::UnknownVersion,
+        }
+    }
+
+    /// The installed version, if there is one and it is known.
+    pub fn installed_version(&self, registry: &Registry) -> Option<Version> {
+        match self.installed(registry) {
+            Installed::Version(version) => Some(version),
+            _ => None,
+        }
+    }
+
+    /// True when the tool is missing, its version is unknown or older than the latest release.
+    pub fn is_update_available(&self, registry: &Registry) -> bool {
+        let installed_version = match self.installed(registry) {
+            Installed::Version(version) => version,
+            Installed::No | Installed::UnknownVersion => return true,
+        };
+
+        let latest_version = match self.latest().parsed_version() {
+            Some(version) => version,
+            None => return false,
+        };
+
+        if installed_version > latest_version {
+            eprintln!("Something is wrong, installed {}, the latest available is {}.", installed_version, latest_version);
+        }
+
+        installed_version < latest_version
+    }
+
+    /// Whether installing `release` changes anything: for the latest release only an update does,
+    /// a release asked for by version is installed unless it is the installed one. A copy missing from
+    /// the registry, e.g. from an older wrap, is always installed again so that it gets recorded, and so
+    /// is one from another registry.
+    pub fn needs_install(&self, release: &Release, registry: &Registry) -> bool {
+        if registry.get(&self.name).is_none() {
+            return true;
+        }
+
+        if std::ptr::eq(release, self.latest()) {
+            return self.is_update_available(registry);
+        }
+
+        self.installed_version(registry) != release.parsed_version()
+    }
+
+    /// The installed copy compared with the latest release.
+    pub fn state(&self, registry: &Registry) -> ToolState {
+        let entry = registry.get(&self.name).filter(|_| self.installed_from_other_registry(registry).is_none());
+        let installed = self.installed(registry);
+        let latest_version = self.latest().parsed_version();
+
+        let status = match (&installed, &latest_version) {
+            (Installed::No, _) => Status::NotInstalled,
+            (Installed::UnknownVersion, _) => Status::UnknownVersion,
+            (Installed::Version(installed), Some(latest)) if installed < latest => Status::UpdateAvailable,
+            (Installed::Version(installed), Some(latest)) if installed == latest => Status::UpToDate,
+            (Installed::Version(_), _) => Status::Installed,
+        };
+
+        let binary = match entry {
+            Some(entry) => Some(entry.binary.clone()),
+            None if installed != Installed::No => Some(bin_dir().join(&self.name)),
+            None => None,
+        };
+
+        ToolState {
+            tool: self.name.clone(),
+            registry: self.registry.clone(),
+            installed_version: match installed {
+                Installed::Version(version) => Some(version.to_string()),
+                _ => None,
+            },
+            latest_version: self.latest().version.clone(),
+            status,
+            pinned: entry.is_some_and(|entry| entry.pinned),
+            binary,
+        }
+    }
+
+    pub fn install_description(&self, registry: &Registry) -> String {
+        let state = self.state(registry);
+        let installed_version = state.installed_version.unwrap_or_default();
+        let latest_version = state.latest_version;
+
+        match state.status {
+            Status::NotInstalled => "".to_string(),
+            Status::UnknownVersion => "(installed, unknown version)".to_string(),
+            Status::UpdateAvailable if state.pinned => format!("(pinned at {}, latest {})", installed_version, latest_version),
+            Status::UpdateAvailable => format!("(update available: {}-->{})", installed_version, latest_version),
+            Status::UpToDate if state.pinned => format!("(Latest installed {}, pinned)", latest_version),
+            Status::UpToDate => format!("(Latest installed {})", latest_version),
+            Status::Installed => format!("(installed {})", installed_version),
+        }
+    }
+}
+
+impl Product {
+    /// When the manifest was last updated according to its `lastUpdate` field.
+    pub fn last_update(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.last_update).ok().map(|date| date.with_timezone(&Utc))
+    }
+
+    /// e.g. "last updated 2021-01-17 (3 days ago)"
+    pub fn age_description(&self) -> String {
+        match self.last_update() {
+            Some(date) => format!(
+                "last updated {} ({} ago)",
+                date.format("%Y-%m-%d"),
+                cache::format_age(Utc::now() - date)
+            ),
+            None => "last update unknown".to_string(),
+        }
+    }
+
+    /// Looks up `<tool>` or `<registry>/<tool>`. A plain name means the tool from the registry it was
+    /// installed from, or else from the registry with the highest priority.
+    pub fn find_tool(&self, name: &str, registry: &Registry) -> Option<&Tool> {
+        if let Some((tap, name)) = name.split_once('/') {
+            return self.tools.iter().find(|tool| tool.registry == tap && tool.name == name);
+        }
+
+        let installed_from = registry.get(name).and_then(|installed| installed.registry.as_deref());
+        self.tools
+            .iter()
+            .find(|tool| tool.name == name && Some(tool.registry.as_str()) == installed_from)
+            .or_else(|| self.tools.iter().find(|tool| tool.name == name))
+    }
+
+    /// Whether a plain `<tool>` refers to this tool, rather than to one with the same name in another registry.
+    pub fn is_preferred(&self, tool: &Tool, registry: &Registry) -> bool {
+        self.find_tool(&tool.name, registry).is_some_and(|found| std::ptr::eq(found, tool))
+    }
+
+    /// Looks up every `<tool>` or `<tool>@<version>` in the manifest and picks the release to install,
+    /// failing with a usage error if a tool is unknown or has no matching release.
+    pub fn find_tools(&self, specs: &[String], registry: &Registry) -> Result<Vec<(&Tool, &Release)>, WrapError> {
+        let mut found = Vec::new();
+        let mut errors = Vec::new();
+
+        for spec in specs {
+            match self.find_release(spec, registry) {
+                Ok(release) => found.push(release),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(WrapError::Usage(errors.join("\n")));
+        }
+
+        Ok(found)
+    }
+
+    /// Looks up one `<tool>` or `<tool>@<version>`, see [`Product::find_tools`].
+    pub fn find_release(&self, spec: &str, registry: &Registry) -> Result<(&Tool, &Release), WrapError> {
+        let (name, requirement) = parse_tool_spec(spec).map_err(WrapError::Usage)?;
+
+        let tool = self.find_tool(name, registry).ok_or_else(|| {
+            WrapError::Usage(format!("Unknown tool: {}. Run 'wrap list' to see available tools.", name))
+        })?;
+
+        let requirement = match requirement {
+            Some(requirement) => requirement,
+            None => return Ok((tool, tool.latest())),
+        };
+
+        match tool.find_release(&requirement) {
+            Some(release) => Ok((tool, release)),
+            None => {
+                let versions: Vec<&str> = tool.releases().map(|release| release.version.as_str()).collect();
+                Err(WrapError::Usage(format!(
+                    "No release of {} matches {}, available: {}",
+                    name,
+                    requirement,
+                    versions.join(", ")
+                )))
+            }
+        }
+    }
+
+    pub fn installed_tools(&self, registry: &Registry) -> Vec<&Tool> {
+        self.tools
+            .iter()
+            .filter(|tool| self.is_preferred(tool, registry) && tool.is_installed(registry))
+            .collect()
+    }
+
+    pub fn outdated_tools(&self, registry: &Registry) -> Vec<&Tool> {
+        self.installed_tools(registry)
+            .into_iter()
+            .filter(|tool| tool.is_update_available(registry))
+            .collect()
+    }
+
+    pub fn filter_tools_by_user(&self, registry: &Registry) -> Vec<&Tool> {
+        // Print the list of tools with their indices
+        println!("Select one or more programs by their number (separated by space):");
+        for (i, tool) in self.tools.iter().enumerate() {
+            println!("{}) {} [{}] {}", i + 1, tool.name, tool.registry, tool.install_description(registry));
+        }
+
+        let mut selected_indices: Vec<usize>;
+        loop {
+            let mut input = String::new();
+            // end of input, e.g. stdin is not a terminal
+            if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+                return Vec::new();
+            }
+            selected_indices = input
+                .trim()
+                .split(' ')
+                .map(|x| x.parse::<usize>().unwrap_or(usize::MAX))
+                .filter(|&x| x > 0 && x - 1 < self.tools.len())
+                .collect();
+            if !selected_indices.is_empty() {
+                break;
+            }
+            println!("Invalid input. Try again:");
+        }
+        // println!("selected_indices {:#?}", selected_indices); //debug
+
+        // Filter tools by index
+        let filtered_tools = self
+            .tools
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| selected_indices.contains(&(i + 1)))
+            .map(|(_, tool)| tool)
+            .collect::<Vec<_>>();
+
+        filtered_tools
+    }
+}
+
+/// `command` resolved against ~/bin, None if it ends up anywhere else, e.g. `/bin/sh` or `../x`.
+fn program_in_bin_dir(command: &str) -> Option<PathBuf> {
+    let bin_dir = bin_dir().canonicalize().ok()?;
+    let program = bin_dir.join(command).canonicalize().ok()?;
+    program.starts_with(&bin_dir).then_some(program)
+}
+
+/// Splits `<tool>@<version>` into the name and a version requirement. A plain version such as
+/// `0.1.2` means exactly that version, anything else is a semver requirement like `^0.3`.
+fn parse_tool_spec(spec: &str) -> Result<(&str, Option<VersionReq>), String> {
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) => (name, version.trim()),
+        None => return Ok((spec, None)),
+    };
+
+    if let Ok(version) = Version::parse(version.trim_start_matches('v')) {
+        return Ok((name, Some(VersionReq::parse(&format!("={}", version)).map_err(|e| e.to_string())?)));
+    }
+
+    let requirement =
+        VersionReq::parse(version).map_err(|e| format!("Invalid version requirement in {}: {}", spec, e))?;
+
+    Ok((name, Some(requirement)))
+}
+
+impl ManifestSource {
+    /// Accepts http(s) URLs, `file://` URLs and plain paths. A directory means `<dir>/wrap.json`.
+    pub fn parse(location: &str) -> Result<ManifestSource, Box<dyn Error>> {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return Ok(ManifestSource::Url(Url::parse(location)?));
+        }
+
+        let path = match location.strip_prefix("file://") {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(location),
+        };
+
+        if path.is_dir() {
+            return Ok(ManifestSource::File(path.join(MANIFEST_FILENAME)));
+        }
+
+        Ok(ManifestSource::File(path))
+    }
+
+    /// The source with a local manifest given by its full path, as recorded in the install registry:
+    /// `m/wrap.json` means nothing when wrap runs in another directory.
+    pub fn canonical(&self) -> ManifestSource {
+        match self {
+            ManifestSource::Url(url) => ManifestSource::Url(url.clone()),
+            ManifestSource::File(path) => ManifestSource::File(
+                fs::canonicalize(path).or_else(|_| std::path::absolute(path)).unwrap_or_else(|_| path.clone()),
+            ),
+        }
+    }
+
+    /// Loads and parses the manifest. Unless `trusted_keys` is None (--insecure or no key), the detached
+    /// `<manifest>.sig` must be a valid signature from one of the keys. Remote manifests go through
+    /// the cache, and only the cache is used when `offline`.
+    pub async fn load(
+        &self,
+        client: &ManifestClient,
+        trusted_keys: Option<&[VerifyingKey]>,
+        offline: bool,
+    ) -> Result<Product, Box<dyn Error>> {
+        let Fetched { content, cached_at } =
+            self.fetch(client, offline).await.map_err(|e| WrapError::ManifestFetch(e.to_string()))?;
+
+        match trusted_keys {
+            Some(keys) => {
+                let signature = self.signature().fetch(client, offline).await.map(|fetched| fetched.content).map_err(|e| {
+                    WrapError::ManifestParse(format!("manifest is not signed ({}), use --insecure to load it anyway", e))
+                })?;
+                signature::verify(&content, &signature, keys).map_err(|e| {
+                    WrapError::ManifestParse(format!("invalid manifest signature: {}, use --insecure to load it anyway", e))
+                })?;
+            }
+            None => eprintln!("Warning: the signature of {} is not verified.", self),
+        }
+
+        let mut product: Product =
+            serde_json::from_slice(&content).map_err(|e| WrapError::ManifestParse(e.to_string()))?;
+
+        if let Some(cached_at) = cached_at {
+            progress!(
+                "Using the cached manifest {}, fetched {} ago, {}.",
+                self,
+                cache::format_age(Utc::now() - cached_at),
+                product.age_description()
+            );
+        }
+
+        let releases = product
+            .tools
+            .iter_mut()
+            .flat_map(|tool| std::iter::once(&mut tool.release).chain(&mut tool.releases))
+            .chain(&mut product.wrap);
+        for release in releases {
+            for asset in &mut release.files {
+                asset.url = self.resolve(&asset.url);
+            }
+            if let Some(archive) = &mut release.archive {
+                archive.url = self.resolve(&archive.url);
+            }
+            for binary in release.binaries.values_mut() {
+                binary.url = self.resolve(&binary.url);
+            }
+        }
+
+        Ok(product)
+    }
+
+    async fn fetch(&self, client: &ManifestClient, offline: bool) -> Result<Fetched, Box<dyn Error>> {
+        match self {
+            ManifestSource::Url(url) => cache::fetch(client, url, offline).await,
+            ManifestSource::File(path) => Ok(Fetched {
+                content: fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+                cached_at: None,
+            }),
+        }
+    }
+
+    /// Location of the detached signature, e.g. wrap.json.sig
+    fn signature(&self) -> ManifestSource {
+        match self {
+            ManifestSource::Url(url) => {
+                let mut url = url.clone();
+                url.set_path(&format!("{}.{}", url.path(), SIGNATURE_EXTENSION));
+                ManifestSource::Url(url)
+            }
+            ManifestSource::File(path) => {
+                let mut path = path.clone().into_os_string();
+                path.push(".");
+                path.push(SIGNATURE_EXTENSION);
+                ManifestSource::File(PathBuf::from(path))
+            }
+        }
+    }
+
+    /// Resolves an asset url relative to the manifest location, so a manifest can refer to files next to it.
+    fn resolve(&self, url: &str) -> String {
+        if url.contains("://") || Path::new(url).is_absolute() {
+            return url.to_string();
+        }
+
+        match self {
+            ManifestSource::Url(base) => base.join(url).map(|u| u.to_string()).unwrap_or_else(|_| url.to_string()),
+            ManifestSource::File(path) => {
+                let dir = path.parent().unwrap_or_else(|| Path::new("."));
+                dir.join(url).to_string_lossy().to_string()
+            }
+        }
+    }
+}
+
+impl fmt::Display for ManifestSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManifestSource::Url(url) => write!(f, "{}", url),
+            ManifestSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_manifest_source() {
+        match ManifestSource::parse("https://example.com/tools/wrap.json").unwrap() {
+            ManifestSource::Url(url) => assert_eq!(url.as_str(), "https://example.com/tools/wrap.json"),
+            other => panic!("expected a URL, got {}", other),
+        }
+
+        match ManifestSource::parse("file:///srv/tools/wrap.json").unwrap() {
+            ManifestSource::File(path) => assert_eq!(path, PathBuf::from("/srv/tools/wrap.json")),
+            other => panic!("expected a file, got {}", other),
+        }
+
+        match ManifestSource::parse("../tools.json").unwrap() {
+            ManifestSource::File(path) => assert_eq!(path, PathBuf::from("../tools.json")),
+            other => panic!("expected a file, got {}", other),
+        }
+
+        let dir = std::env::temp_dir();
+        match ManifestSource::parse(&dir.to_string_lossy()).unwrap() {
+            ManifestSource::File(path) => assert_eq!(path, dir.join(MANIFEST_FILENAME)),
+            other => panic!("expected a file, got {}", other),
+        }
+
+        assert!(ManifestSource::parse("https://").is_err());
+    }
+
+    #[test]
+    fn resolve_against_url() {
+        let source = ManifestSource::parse("https://example.com/tools/wrap.json").unwrap();
+        assert_eq!(source.resolve("src/main.rs"), "https://example.com/tools/src/main.rs");
+        assert_eq!(source.resolve("../other/main.rs"), "https://example.com/other/main.rs");
+        assert_eq!(source.resolve("https://cdn.example.com/main.rs"), "https://cdn.example.com/main.rs");
+        assert_eq!(source.resolve("/srv/main.rs"), "/srv/main.rs");
+    }
+
+    #[test]
+    fn resolve_against_file() {
+        let source = ManifestSource::File(PathBuf::from("/srv/tools/wrap.json"));
+        assert_eq!(source.resolve("src/main.rs"), "/srv/tools/src/main.rs");
+        assert_eq!(source.resolve("https://cdn.example.com/main.rs"), "https://cdn.example.com/main.rs");
+
+        let source = ManifestSource::File(PathBuf::from("wrap.json"));
+        assert_eq!(source.resolve("src/main.rs"), "src/main.rs");
+    }
+
+    #[test]
+    fn parse_tool_spec_versions() {
+        assert_eq!(parse_tool_spec("litegallery").unwrap(), ("litegallery", None));
+
+        // a plain version is an exact one, not the caret requirement semver would make of it
+        let (name, requirement) = parse_tool_spec("lumixbackup@0.1.2").unwrap();
+        let requirement = requirement.unwrap();
+        assert_eq!(name, "lumixbackup");
+        assert_eq!(requirement, VersionReq::parse("=0.1.2").unwrap());
+        assert!(requirement.matches(&Version::new(0, 1, 2)));
+        assert!(!requirement.matches(&Version::new(0, 1, 3)));
+
+        let (_, requirement) = parse_tool_spec("lumixbackup@v0.1.2").unwrap();
+        assert_eq!(requirement.unwrap(), VersionReq::parse("=0.1.2").unwrap());
+
+        let (name, requirement) = parse_tool_spec("litegallery@^0.3").unwrap();
+        let requirement = requirement.unwrap();
+        assert_eq!(name, "litegallery");
+        assert!(requirement.matches(&Version::new(0, 3, 9)));
+        assert!(!requirement.matches(&Version::new(0, 4, 0)));
+
+        let error = parse_tool_spec("litegallery@bogus").unwrap_err();
+        assert!(error.contains("litegallery@bogus"), "{}", error);
+    }
+}
@@ -0,0 +1,34 @@
+use std::io;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `--json`: stdout then carries nothing but the JSON result.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Stdout for child processes whose output is shown to the user, e.g. rustup.
+pub fn child_stdout() -> Stdio {
+    if is_json() {
+        io::stderr().into()
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Like `println!`, for progress messages: they go to stderr while stdout is reserved for JSON.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::output::is_json() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::data_dir;
+
+static REGISTRY_FILENAME: &str = "installed.json";
+
+/// What wrap knows about a tool it has installed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledTool {
+    pub name: String,
+    pub version: String,
+    pub installed_at: DateTime<Utc>,
+    pub binary: PathBuf,
+    /// BLAKE3 hash of the installed binary
+    pub binary_hash: String,
+    /// Manifest the tool was installed from
+    pub source: String,
+    /// Name of the registry that manifest belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// Pinned tools are left out of `wrap update --all`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+}
+
+/// The list of tools installed by wrap, stored in ~/.local/share/wrap/installed.json
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    tools: Vec<InstalledTool>,
+}
+
+impl Registry {
+    pub fn path() -> PathBuf {
+        data_dir().join(REGISTRY_FILENAME)
+    }
+
+    /// Loads the registry, or an empty one if nothing was installed yet.
+    pub fn load() -> Result<Registry, Box<dyn Error>> {
+        let path = Registry::path();
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let registry = serde_json::from_str(&content)
+            .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+
+        Ok(registry)
+    }
+
+    /// Writes the registry to a temporary file first, so a crash never leaves it half written.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = Registry::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    pub fn tools(&self) -> &[InstalledTool] {
+        &self.tools
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InstalledTool> {
+        self.tools.iter().find(|tool| tool.name == name)
+    }
+
+    /// Adds the tool, replacing any previous entry with the same name.
+    pub fn insert(&mut self, tool: InstalledTool) {
+        self.remove(&tool.name);
+        self.tools.push(tool);
+        self.tools.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<InstalledTool> {
+        let index = self.tools.iter().position(|tool| tool.name == name)?;
+        Some(self.tools.remove(index))
+    }
+}
+
+pub fn hash_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let content = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(blake3::hash(&content).to_hex().to_string())
+}
@@ -0,0 +1,116 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::{config_dir, Config};
+
+/// Public key the official wrap.json is signed with (base64 encoded ed25519 key). Only the maintainer holds the
+/// private key, so it is given when building a release: `WRAP_BUILTIN_PUBLIC_KEY=<key> cargo build --release`.
+static BUILTIN_PUBLIC_KEY: Option<&str> = option_env!("WRAP_BUILTIN_PUBLIC_KEY");
+static SIGNING_KEY_FILENAME: &str = "signing.key";
+pub static SIGNATURE_EXTENSION: &str = "sig";
+
+/// The key wrap was built with, if any. Self-updates build the new wrap with the same key.
+pub fn builtin_public_key() -> Option<&'static str> {
+    BUILTIN_PUBLIC_KEY.filter(|key| !key.is_empty())
+}
+
+/// Keys a manifest signature is accepted from: the built-in key plus `public-key` from the config file.
+/// Empty if wrap was built without a key and none is configured.
+pub fn trusted_keys(config: &Config) -> Result<Vec<VerifyingKey>, Box<dyn Error>> {
+    let mut keys = Vec::new();
+    if let Some(key) = builtin_public_key() {
+        keys.push(parse_public_key(key).map_err(|e| format!("invalid built-in public key: {}", e))?);
+    }
+    if let Some(key) = &config.public_key {
+        keys.push(parse_public_key(key).map_err(|e| format!("invalid public-key in config: {}", e))?);
+    }
+
+    Ok(keys)
+}
+
+pub fn parse_public_key(key: &str) -> Result<VerifyingKey, Box<dyn Error>> {
+    let bytes: [u8; 32] = BASE64
+        .decode(key.trim())?
+        .try_into()
+        .map_err(|_| "public key must be 32 bytes")?;
+
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// Checks a detached, base64 encoded signature of `content` against any of the trusted keys.
+pub fn verify(content: &[u8], signature: &[u8], keys: &[VerifyingKey]) -> Result<(), Box<dyn Error>> {
+    let signature = String::from_utf8_lossy(signature);
+    let bytes: [u8; 64] = BASE64
+        .decode(signature.trim())
+        .map_err(|e| format!("malformed signature: {}", e))?
+        .try_into()
+        .map_err(|_| "signature must be 64 bytes")?;
+    let signature = Signature::from_bytes(&bytes);
+
+    if keys.iter().any(|key| key.verify(content, &signature).is_ok()) {
+        Ok(())
+    } else {
+        Err("signature does not match any trusted public key".into())
+    }
+}
+
+/// ~/.config/wrap/signing.key
+pub fn default_signing_key_path() -> PathBuf {
+    config_dir().join(SIGNING_KEY_FILENAME)
+}
+
+/// Creates a new private key file and returns the matching base64 public key.
+pub fn generate_key(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| format!("failed to generate key: {}", e))?;
+    let key = SigningKey::from_bytes(&seed);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = create_private_file(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => format!("{} already exists, refusing to overwrite it", path.display()),
+        _ => format!("{}: {}", path.display(), e),
+    })?;
+    writeln!(file, "{}", BASE64.encode(key.to_bytes()))?;
+
+    Ok(BASE64.encode(key.verifying_key().to_bytes()))
+}
+
+/// Writes `<manifest>.sig` next to the manifest, signed with the private key file.
+pub fn sign_file(manifest: &Path, key_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let key = fs::read_to_string(key_path).map_err(|e| format!("{}: {}", key_path.display(), e))?;
+    let bytes: [u8; 32] = BASE64
+        .decode(key.trim())?
+        .try_into()
+        .map_err(|_| "private key must be 32 bytes")?;
+    let key = SigningKey::from_bytes(&bytes);
+
+    let content = fs::read(manifest).map_err(|e| format!("{}: {}", manifest.display(), e))?;
+    let signature = key.sign(&content);
+
+    let mut signature_path = manifest.as_os_str().to_owned();
+    signature_path.push(".");
+    signature_path.push(SIGNATURE_EXTENSION);
+    let signature_path = PathBuf::from(signature_path);
+    fs::write(&signature_path, format!("{}\n", BASE64.encode(signature.to_bytes())))?;
+
+    Ok(signature_path)
+}
+
+/// Creates a new file only the owner can read, failing if it exists.
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
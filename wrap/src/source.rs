@@ -0,0 +1,354 @@
+use flate2::read::GzDecoder;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use crate::download::Downloader;
+use crate::error::WrapError;
+use crate::manifest::{Archive, Asset, Binary, GitSource};
+
+/// Target triple this wrap was built for, e.g. x86_64-unknown-linux-gnu
+pub static HOST_TARGET: &str = env!("WRAP_TARGET");
+
+/// Creates a new cargo project in `project_dir` and downloads the listed files into it. Files of an
+/// earlier version that the release no longer lists are never part of the build.
+pub async fn fetch_files(
+    name: &str,
+    files: &[Asset],
+    project_dir: &Path,
+    downloader: &Downloader,
+) -> Result<(), Box<dyn Error>> {
+    // create a new rust project
+    progress!("Creating a new rust project");
+    let output = Command::new("cargo")
+        .arg("new")
+        .arg("--name")
+        .arg(name)
+        .arg(project_dir)
+        .output()
+        .map_err(|e| format!("failed to run cargo: {}", e))?;
+    progress!("{}", String::from_utf8_lossy(&output.stdout));
+    if !output.status.success() {
+        return Err(WrapError::Build(format!("cargo new failed: {}", String::from_utf8_lossy(&output.stderr))).into());
+    }
+
+    progress!("Downloading up to date files from github");
+    let mut downloads = Vec::new();
+    for asset in files {
+        let path = asset_path(project_dir, asset)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        downloads.push((asset.url.as_str(), &asset.checksums, path));
+    }
+
+    let mut checksum_failed = false;
+    let errors: Vec<String> = downloader
+        .download_all(downloads)
+        .await
+        .into_iter()
+        .zip(files)
+        .filter_map(|(result, asset)| {
+            let error = result.err()?;
+            checksum_failed |= matches!(error.downcast_ref::<WrapError>(), Some(WrapError::Checksum(_)));
+            Some(format!("{}: {}", asset.filename, error))
+        })
+        .collect();
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        // this includes files that do not match the manifest checksums
+        let message = format!(
+            "{} of {} files could not be downloaded or verified, nothing was built",
+            errors.len(),
+            files.len()
+        );
+        return Err(if checksum_failed { WrapError::Checksum(message) } else { WrapError::Download(message) }.into());
+    }
+    progress!("All files downlaoded.");
+
+    Ok(())
+}
+
+/// Where a listed file goes in `project_dir`. The manifest must not be able to write anywhere else, so
+/// `location` may only name subfolders and `filename` a plain file name.
+fn asset_path(project_dir: &Path, asset: &Asset) -> Result<PathBuf, Box<dyn Error>> {
+    let location = Path::new(&asset.location);
+    let filename = Path::new(&asset.filename);
+    let location_ok = location.components().all(|part| matches!(part, Component::Normal(_) | Component::CurDir));
+    let filename_ok = matches!(filename.components().collect::<Vec<_>>()[..], [Component::Normal(_)]);
+    if !location_ok || !filename_ok {
+        return Err(WrapError::ManifestParse(format!(
+            "file {} in {} points outside the project",
+            asset.filename, asset.location
+        ))
+        .into());
+    }
+
+    Ok(project_dir.join(location).join(filename))
+}
+
+/// Downloads and verifies the archive, then unpacks the project directory from it into `project_dir`.
+pub async fn fetch_archive(archive: &Archive, project_dir: &Path, downloader: &Downloader) -> Result<(), Box<dyn Error>> {
+    if archive.checksums.is_empty() {
+        return Err(WrapError::Checksum(format!("archive {} has no sha256 or blake3 checksum in the manifest", archive.url)).into());
+    }
+
+    let work_dir = work_dir(project_dir);
+    if work_dir.exists() {
+        fs::remove_dir_all(&work_dir)?;
+    }
+
+    let result = unpack_archive(archive, project_dir, &work_dir, downloader).await;
+    if work_dir.exists() {
+        fs::remove_dir_all(&work_dir)?;
+    }
+
+    result
+}
+
+async fn unpack_archive(
+    archive: &Archive,
+    project_dir: &Path,
+    work_dir: &Path,
+    downloader: &Downloader,
+) -> Result<(), Box<dyn Error>> {
+    let format = ArchiveFormat::from_url(&archive.url)?;
+    let contents = work_dir.join("contents");
+    fs::create_dir_all(&contents)?;
+
+    progress!("Downloading {}", archive.url);
+    let file = work_dir.join(format!("archive.{}", format.extension()));
+    downloader.download(&archive.url, &archive.checksums, &file).await?;
+
+    progress!("Unpacking {}", archive.url);
+    format.unpack(&file, &contents)?;
+
+    let root = strip_single_directory(&contents)?;
+    move_project(&root, archive.path.as_deref(), project_dir)
+}
+
+/// Downloads and verifies a prebuilt binary into `dir`, unpacking it first if it comes in an
+/// archive. Returns the path of the executable.
+pub async fn fetch_binary(
+    name: &str,
+    binary: &Binary,
+    dir: &Path,
+    downloader: &Downloader,
+) -> Result<PathBuf, Box<dyn Error>> {
+    if binary.checksums.is_empty() {
+        return Err(WrapError::Checksum(format!("binary {} has no sha256 or blake3 checksum in the manifest", binary.url)).into());
+    }
+
+    let format = ArchiveFormat::detect(&binary.url);
+    fs::create_dir_all(dir)?;
+
+    progress!("Downloading {}", binary.url);
+    let file = match &format {
+        Some(format) => dir.join(format!("download.{}", format.extension())),
+        None => dir.join(name),
+    };
+    downloader.download(&binary.url, &binary.checksums, &file).await?;
+
+    let executable = match format {
+        Some(format) => {
+            progress!("Unpacking {}", binary.url);
+            let contents = dir.join("contents");
+            format.unpack(&file, &contents)?;
+            fs::remove_file(&file)?;
+
+            let root = strip_single_directory(&contents)?;
+            let executable = root.join(binary.path.as_deref().unwrap_or(name));
+            if !executable.canonicalize()?.starts_with(root.canonicalize()?) {
+                return Err(format!("path {} points outside the archive", executable.display()).into());
+            }
+            if !executable.is_file() {
+                return Err(format!("{} does not contain {}", binary.url, binary.path.as_deref().unwrap_or(name)).into());
+            }
+            executable
+        }
+        None => file,
+    };
+
+    make_executable(&executable)?;
+
+    Ok(executable)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Clones the repository at the given tag or commit into `project_dir`.
+pub fn fetch_git(git: &GitSource, project_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let work_dir = work_dir(project_dir);
+    if work_dir.exists() {
+        fs::remove_dir_all(&work_dir)?;
+    }
+
+    let result = clone_repository(git, project_dir, &work_dir);
+    if work_dir.exists() {
+        fs::remove_dir_all(&work_dir)?;
+    }
+
+    result
+}
+
+fn clone_repository(git: &GitSource, project_dir: &Path, work_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let checkout = work_dir.join("repository");
+    let target = checkout.to_string_lossy();
+
+    progress!("Cloning {}", git.url);
+    match (&git.tag, &git.commit) {
+        (Some(tag), _) => run_git(&["clone", "--quiet", "--depth", "1", "--branch", tag, &git.url, &target], None)?,
+        (None, Some(commit)) => {
+            run_git(&["clone", "--quiet", "--no-checkout", &git.url, &target], None)?;
+            run_git(&["checkout", "--quiet", "--detach", commit], Some(&checkout))?;
+        }
+        (None, None) => return Err(format!("git source {} needs a tag or a commit", git.url).into()),
+    }
+
+    // a tag can be moved, the commit cannot
+    if let Some(commit) = &git.commit {
+        let output = Command::new("git").args(["rev-parse", "HEAD"]).current_dir(&checkout).output()?;
+        let head = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !head.starts_with(commit.as_str()) {
+            let message = format!("{} points to {}, the manifest expects commit {}", git.url, head, commit);
+            return Err(WrapError::Checksum(message).into());
+        }
+    }
+
+    fs::remove_dir_all(checkout.join(".git"))?;
+    move_project(&checkout, git.path.as_deref(), project_dir)
+}
+
+/// Runs git, inside `dir` when given.
+fn run_git(args: &[&str], dir: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut command = Command::new("git");
+    command.args(args);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    let output = command.output().map_err(|e| WrapError::Download(format!("failed to run git: {}", e)))?;
+    if !output.status.success() {
+        let message = format!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim());
+        return Err(WrapError::Download(message).into());
+    }
+
+    Ok(())
+}
+
+/// Moves `root/path` (or `root`) to `project_dir` after checking it is a cargo project.
+fn move_project(root: &Path, path: Option<&str>, project_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let source = match path {
+        Some(path) => root.join(path),
+        None => root.to_path_buf(),
+    };
+
+    // keep the project inside what was downloaded
+    if !source.canonicalize()?.starts_with(root.canonicalize()?) {
+        return Err(format!("path {} points outside the source", source.display()).into());
+    }
+    if !source.join("Cargo.toml").exists() {
+        return Err(format!("no Cargo.toml found in {}", path.unwrap_or("the source root")).into());
+    }
+
+    fs::rename(&source, project_dir)?;
+
+    Ok(())
+}
+
+/// GitHub style archives wrap everything in one `<repo>-<ref>` directory.
+fn strip_single_directory(dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let entries: Vec<_> = fs::read_dir(dir)?.filter_map(|entry| entry.ok()).collect();
+    if entries.len() == 1 && entries[0].path().is_dir() {
+        return Ok(entries[0].path());
+    }
+
+    Ok(dir.to_path_buf())
+}
+
+/// Scratch space next to the project directory, e.g. ~/cli-projects/.litegallery.download
+fn work_dir(project_dir: &Path) -> PathBuf {
+    project_dir.with_extension("download")
+}
+
+enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn from_url(url: &str) -> Result<ArchiveFormat, String> {
+        ArchiveFormat::detect(url).ok_or_else(|| format!("unsupported archive {}, expected .tar.gz, .tgz or .zip", url))
+    }
+
+    /// Tells archives from plain files by the extension of the url.
+    fn detect(url: &str) -> Option<ArchiveFormat> {
+        let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if path.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    fn unpack(&self, file: &Path, destination: &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(destination)?;
+        match self {
+            ArchiveFormat::TarGz => tar::Archive::new(GzDecoder::new(File::open(file)?)).unpack(destination)?,
+            ArchiveFormat::Zip => zip::ZipArchive::new(File::open(file)?)?.extract(destination)?,
+        }
+
+        Ok(())
+    }
+
+    fn extension(&self) -> &str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Checksums;
+
+    fn asset(location: &str, filename: &str) -> Asset {
+        Asset {
+            location: location.to_string(),
+            filename: filename.to_string(),
+            url: String::new(),
+            checksums: Checksums::default(),
+        }
+    }
+
+    #[test]
+    fn asset_path_stays_in_the_project() {
+        let project = Path::new("/home/me/cli-projects/.tool.staging");
+        assert_eq!(asset_path(project, &asset("", "Cargo.toml")).unwrap(), project.join("Cargo.toml"));
+        assert_eq!(asset_path(project, &asset("src/bin", "main.rs")).unwrap(), project.join("src/bin/main.rs"));
+
+        assert!(asset_path(project, &asset("/home/me", ".bashrc")).is_err());
+        assert!(asset_path(project, &asset("../..", ".bashrc")).is_err());
+        assert!(asset_path(project, &asset("src/../../..", "x")).is_err());
+        assert!(asset_path(project, &asset("src", "../../x")).is_err());
+        assert!(asset_path(project, &asset("src", "/etc/x")).is_err());
+        assert!(asset_path(project, &asset("src", "")).is_err());
+    }
+}
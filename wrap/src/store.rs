@@ -0,0 +1,79 @@
+use semver::Version;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::data_dir;
+
+static VERSIONS: &str = "versions"; //~/.local/share/wrap/versions
+
+/// ~/.local/share/wrap/versions/<tool>, holding one `<version>/<tool>` binary per kept version
+pub fn tool_dir(name: &str) -> PathBuf {
+    data_dir().join(VERSIONS).join(name)
+}
+
+pub fn binary_path(name: &str, version: &Version) -> PathBuf {
+    tool_dir(name).join(version.to_string()).join(name)
+}
+
+/// Copies a freshly built binary into the store and returns where it was put.
+pub fn add(name: &str, version: &Version, binary: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let stored = binary_path(name, version);
+    if let Some(dir) = stored.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = stored.with_extension("tmp");
+    fs::copy(binary, &tmp).map_err(|e| format!("failed to copy {}: {}", binary.display(), e))?;
+    fs::rename(&tmp, &stored)?;
+
+    Ok(stored)
+}
+
+/// Stored versions of a tool, newest first.
+pub fn versions(name: &str) -> Vec<Version> {
+    let mut versions: Vec<Version> = match fs::read_dir(tool_dir(name)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Version::parse(&entry.file_name().to_string_lossy()).ok())
+            .filter(|version| binary_path(name, version).exists())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    versions.sort_by(|a, b| b.cmp(a));
+
+    versions
+}
+
+/// Deletes all but the newest `keep` versions. The active version is always kept.
+pub fn prune(name: &str, keep: usize, active: &Version) -> Result<(), Box<dyn Error>> {
+    for version in versions(name).iter().skip(keep.max(1)) {
+        if version != active {
+            fs::remove_dir_all(tool_dir(name).join(version.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes every stored version of a tool.
+pub fn remove(name: &str) -> Result<(), Box<dyn Error>> {
+    let dir = tool_dir(name);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+
+    Ok(())
+}
+
+/// Puts a stored binary in place as `target`. The copy is written next to the target first and
+/// then renamed over it, so the target is always either the old or the new binary.
+pub fn activate(stored: &Path, target: &Path) -> Result<(), Box<dyn Error>> {
+    let file_name = target.file_name().ok_or("invalid target path")?.to_string_lossy();
+    let tmp = target.with_file_name(format!(".{}.wrap-tmp", file_name));
+
+    fs::copy(stored, &tmp).map_err(|e| format!("failed to copy {}: {}", stored.display(), e))?;
+    fs::rename(&tmp, target).map_err(|e| format!("failed to replace {}: {}", target.display(), e))?;
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::error::Error;
+
+use crate::cache::ManifestClient;
+use crate::config::Config;
+use crate::error::WrapError;
+use crate::manifest::{ManifestSource, Product, DEFAULT_MANIFEST};
+
+/// Name of the registry given by --manifest, WRAP_MANIFEST or `manifest` in the config file.
+pub static DEFAULT_TAP: &str = "default";
+
+/// A registry of tools: a named manifest with a priority, e.g. a team's wrap.json next to the public one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tap {
+    pub name: String,
+    /// Manifest location (URL, file:// URL, file or directory)
+    pub url: String,
+    /// When several registries have a tool with the same name, the highest priority wins
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl Tap {
+    pub fn source(&self) -> Result<ManifestSource, Box<dyn Error>> {
+        ManifestSource::parse(&self.url)
+    }
+}
+
+/// The registries to load, highest priority first. The default registry comes from `manifest`
+/// (--manifest or WRAP_MANIFEST), the config file or the public manifest, in that order. A
+/// `[[registries]]` entry named "default" sets its priority, and its url unless `manifest` is given.
+pub fn taps(config: &Config, manifest: Option<String>) -> Result<Vec<Tap>, Box<dyn Error>> {
+    let configured = config.registries();
+    for (i, tap) in configured.iter().enumerate() {
+        if tap.name.is_empty() || tap.name.contains('/') {
+            return Err(format!("invalid registry name '{}' in {}", tap.name, Config::path().display()).into());
+        }
+        if configured[..i].iter().any(|other| other.name == tap.name) {
+            return Err(format!("registry {} is listed twice in {}", tap.name, Config::path().display()).into());
+        }
+    }
+
+    let configured_default = configured.iter().find(|tap| tap.name == DEFAULT_TAP);
+    let url = manifest
+        .or_else(|| configured_default.map(|tap| tap.url.clone()))
+        .or_else(|| config.manifest.clone())
+        .unwrap_or_else(|| DEFAULT_MANIFEST.to_string());
+
+    let mut taps = vec![Tap {
+        name: DEFAULT_TAP.to_string(),
+        url,
+        priority: configured_default.map(|tap| tap.priority).unwrap_or(0),
+    }];
+    taps.extend(configured.iter().filter(|tap| tap.name != DEFAULT_TAP).cloned());
+
+    // stable, so the default registry and then the config order break ties
+    taps.sort_by_key(|tap| Reverse(tap.priority));
+
+    Ok(taps)
+}
+
+/// Loads every registry and merges their tools, highest priority first. A registry that fails to
+/// load is reported and skipped as long as another one loads.
+pub async fn load_all(
+    taps: &[Tap],
+    client: &ManifestClient,
+    trusted_keys: Option<&[VerifyingKey]>,
+    offline: bool,
+) -> Result<Product, Box<dyn Error>> {
+    let mut merged = Product { last_update: String::new(), tools: Vec::new(), wrap: None };
+    let mut errors = Vec::new();
+    let mut unparsable = 0;
+
+    for tap in taps {
+        let source = tap.source()?;
+        match source.load(client, trusted_keys, offline).await {
+            Ok(mut product) => {
+                for tool in &mut product.tools {
+                    tool.registry = tap.name.clone();
+                }
+                // another registry must not be able to replace wrap itself
+                if tap.name == DEFAULT_TAP {
+                    merged.wrap = product.wrap.take();
+                }
+                merged.last_update = merged.last_update.max(product.last_update);
+                merged.tools.extend(product.tools);
+            }
+            Err(e) => {
+                unparsable += matches!(e.downcast_ref::<WrapError>(), Some(WrapError::ManifestParse(_))) as usize;
+                errors.push(format!("failed to load manifest from {}: {}", source, e));
+            }
+        }
+    }
+
+    if errors.len() == taps.len() {
+        let message = errors.join("\n");
+        // a manifest that was fetched but cannot be used is the more specific failure
+        return Err(if unparsable == errors.len() { WrapError::ManifestParse(message) } else { WrapError::ManifestFetch(message) }.into());
+    }
+    for error in errors {
+        eprintln!("Warning: {}", error);
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap(name: &str, url: &str, priority: i32) -> Tap {
+        Tap { name: name.to_string(), url: url.to_string(), priority }
+    }
+
+    fn config(registries: Vec<Tap>) -> Config {
+        Config { registries: Some(registries), ..Config::default() }
+    }
+
+    fn names(taps: &[Tap]) -> Vec<&str> {
+        taps.iter().map(|tap| tap.name.as_str()).collect()
+    }
+
+    #[test]
+    fn taps_by_priority() {
+        let config = config(vec![tap("low", "low.json", -1), tap("team", "team.json", 5), tap("other", "b.json", 0)]);
+        let loaded = taps(&config, None).unwrap();
+
+        // the default registry goes first among equals, then the config order
+        assert_eq!(names(&loaded), ["team", DEFAULT_TAP, "other", "low"]);
+        assert_eq!(loaded[1].url, DEFAULT_MANIFEST);
+    }
+
+    #[test]
+    fn taps_default_entry_sets_priority_and_url() {
+        let config = config(vec![tap("team", "team.json", 5), tap(DEFAULT_TAP, "mirror.json", 10)]);
+
+        let loaded = taps(&config, None).unwrap();
+        assert_eq!(names(&loaded), [DEFAULT_TAP, "team"]);
+        assert_eq!(loaded[0].url, "mirror.json");
+
+        // --manifest wins over the url, the priority stays
+        let loaded = taps(&config, Some("local.json".to_string())).unwrap();
+        assert_eq!(names(&loaded), [DEFAULT_TAP, "team"]);
+        assert_eq!(loaded[0].url, "local.json");
+
+        // `manifest` in the config file is only used without a default entry
+        let config = Config { manifest: Some("configured.json".to_string()), ..config };
+        assert_eq!(taps(&config, None).unwrap()[0].url, "mirror.json");
+        let config = Config { registries: None, ..config };
+        assert_eq!(taps(&config, None).unwrap()[0].url, "configured.json");
+    }
+
+    #[test]
+    fn taps_rejects_bad_names() {
+        assert!(taps(&config(vec![tap("team", "a.json", 0), tap("team", "b.json", 1)]), None).is_err());
+        assert!(taps(&config(vec![tap("a/b", "a.json", 0)]), None).is_err());
+        assert!(taps(&config(vec![tap("", "a.json", 0)]), None).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use semver::Version;
+use std::error::Error;
+use std::process::Command;
+
+use crate::manifest::Release;
+use crate::output;
+
+static STABLE: &str = "stable";
+
+/// Makes sure a rust toolchain that can build the release is available, and returns the one to
+/// pass to cargo as `+<toolchain>`, or None for the default. rustup only runs when the installed
+/// rustc does not satisfy the release's `rustVersion` or its pinned `toolchain` is missing, and
+/// never when `allow_update` is false.
+pub fn prepare(release: &Release, allow_update: bool) -> Result<Option<String>, Box<dyn Error>> {
+    let required = match &release.rust_version {
+        Some(required) => Some(parse_rust_version(required)?),
+        None => None,
+    };
+
+    let installed = rustc_version(None).ok_or("rustc was not found, install rust from https://rustup.rs")?;
+    progress!("Current Rust version: {}", installed);
+
+    let toolchain = match (&required, &release.toolchain) {
+        // a pinned toolchain without a minimum version is always used
+        (None, Some(toolchain)) => toolchain,
+        (None, None) => return Ok(None),
+        (Some(required), _) if satisfies(&installed, required) => return Ok(None),
+        (Some(required), None) => return update_stable(&installed, required, allow_update),
+        (Some(_), Some(toolchain)) => toolchain,
+    };
+
+    if rustc_version(Some(toolchain)).is_none() {
+        if !allow_update {
+            eprintln!("Warning: toolchain {} is not installed, trying anyway (updates are disabled).", toolchain);
+            return Ok(Some(toolchain.clone()));
+        }
+        progress!("Installing Rust toolchain {}...", toolchain);
+        rustup(&["toolchain", "install", toolchain, "--profile", "minimal"])?;
+    }
+
+    Ok(Some(toolchain.clone()))
+}
+
+/// Updates the stable toolchain for a release that needs a newer rustc than the installed one. If
+/// the default toolchain is not stable and stays too old, stable is used explicitly.
+fn update_stable(installed: &Version, required: &Version, allow_update: bool) -> Result<Option<String>, Box<dyn Error>> {
+    if !allow_update {
+        eprintln!(
+            "Warning: rust {} or newer is required, found {}. Building anyway (updates are disabled).",
+            required, installed
+        );
+        return Ok(None);
+    }
+
+    progress!("Rust {} or newer is required, updating the stable toolchain...", required);
+    rustup(&["update", STABLE])?;
+
+    if rustc_version(None).is_some_and(|version| satisfies(&version, required)) {
+        return Ok(None);
+    }
+    if rustc_version(Some(STABLE)).is_some_and(|version| satisfies(&version, required)) {
+        return Ok(Some(STABLE.to_string()));
+    }
+
+    Err(format!("rust {} or newer is required, but no installed toolchain provides it", required).into())
+}
+
+fn rustup(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("rustup")
+        .args(args)
+        .stdout(output::child_stdout())
+        .status()
+        .map_err(|e| format!("failed to run rustup: {}", e))?;
+    if !status.success() {
+        return Err(format!("rustup {} failed, please update rust manually", args.join(" ")).into());
+    }
+
+    Ok(())
+}
+
+/// Version of `rustc`, or of `rustc +<toolchain>`. None if it cannot be run.
+fn rustc_version(toolchain: Option<&str>) -> Option<Version> {
+    let mut command = Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        command.arg(format!("+{}", toolchain));
+    }
+    let output = command.arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // e.g. "rustc 1.75.0 (82e1608df 2023-12-21)"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Version::parse(stdout.split_whitespace().nth(1)?).ok()
+}
+
+/// Accepts "1.70" as well as "1.70.0".
+fn parse_rust_version(version: &str) -> Result<Version, String> {
+    let version = version.trim();
+    let full = match version.matches('.').count() {
+        1 => format!("{}.0", version),
+        _ => version.to_string(),
+    };
+
+    Version::parse(&full).map_err(|e| format!("invalid rustVersion {}: {}", version, e))
+}
+
+/// Nightly and beta builds count as their version number.
+fn satisfies(installed: &Version, required: &Version) -> bool {
+    (installed.major, installed.minor, installed.patch) >= (required.major, required.minor, required.patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rust_version_fills_in_the_patch() {
+        assert_eq!(parse_rust_version("1.70").unwrap(), Version::new(1, 70, 0));
+        assert_eq!(parse_rust_version(" 1.75.1 ").unwrap(), Version::new(1, 75, 1));
+        assert!(parse_rust_version("1").is_err());
+        assert!(parse_rust_version("latest").is_err());
+    }
+
+    #[test]
+    fn satisfies_ignores_pre_releases() {
+        let required = Version::new(1, 70, 0);
+        assert!(satisfies(&Version::new(1, 70, 0), &required));
+        assert!(satisfies(&Version::new(1, 75, 0), &required));
+        assert!(!satisfies(&Version::new(1, 69, 9), &required));
+
+        // a nightly 1.70.0 counts as 1.70.0, which semver would put before it
+        let nightly = Version::parse("1.70.0-nightly").unwrap();
+        assert!(satisfies(&nightly, &required));
+        assert!(!satisfies(&Version::parse("1.69.0-nightly").unwrap(), &required));
+    }
+}
@@ -0,0 +1,77 @@
+use regex::Regex;
+use semver::Version;
+use std::path::Path;
+use std::process::Command;
+
+/// Finds a version in text such as `--version` output or a manifest `version`. With a regex, the
+/// first capture group (or the whole match) is parsed; otherwise the first word that is a semver,
+/// with or without a leading "v". Returns None instead of failing.
+pub fn parse(text: &str, regex: Option<&Regex>) -> Option<Version> {
+    if let Some(regex) = regex {
+        let captures = regex.captures(text)?;
+        let found = captures.get(1).or_else(|| captures.get(0))?;
+        return parse_word(found.as_str());
+    }
+
+    text.split_whitespace().find_map(parse_word)
+}
+
+/// Runs `program args...` and parses the version from its output, stdout first, then stderr.
+pub fn detect(program: &Path, args: &[String], regex: Option<&str>) -> Option<Version> {
+    let regex = match regex {
+        Some(regex) => match Regex::new(regex) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("Warning: invalid version regex {}: {}", regex, e);
+                return None;
+            }
+        },
+        None => None,
+    };
+
+    let output = Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    parse(&stdout, regex.as_ref()).or_else(|| parse(&stderr, regex.as_ref()))
+}
+
+fn parse_word(word: &str) -> Option<Version> {
+    let word = word.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    let word = word.strip_prefix('v').unwrap_or(word);
+    Version::parse(word).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_first_version_word() {
+        assert_eq!(parse("litegallery 1.2.3", None), Some(Version::new(1, 2, 3)));
+        assert_eq!(parse("v1.2.3", None), Some(Version::new(1, 2, 3)));
+        assert_eq!(parse("tool (v0.4.0)\n", None), Some(Version::new(0, 4, 0)));
+        assert_eq!(parse("1.2.3-beta.1", None), Some(Version::parse("1.2.3-beta.1").unwrap()));
+    }
+
+    #[test]
+    fn parse_with_regex() {
+        let regex = Regex::new(r"build (\S+) of").unwrap();
+        assert_eq!(parse("lumixbackup 9.9.9, build 0.1.2 of 2021-01-17", Some(&regex)), Some(Version::new(0, 1, 2)));
+
+        // without a capture group the whole match is the version
+        let regex = Regex::new(r"\d+\.\d+\.\d+").unwrap();
+        assert_eq!(parse("version:2.0.1", Some(&regex)), Some(Version::new(2, 0, 1)));
+
+        let regex = Regex::new(r"build (\S+) of").unwrap();
+        assert_eq!(parse("lumixbackup 9.9.9", Some(&regex)), None);
+    }
+
+    #[test]
+    fn parse_garbage() {
+        assert_eq!(parse("", None), None);
+        assert_eq!(parse("command not found", None), None);
+        assert_eq!(parse("1.2 v1 1..2.3 x.y.z", None), None);
+        assert_eq!(parse("\u{0}\u{ffff} \\ 1.2.3.4", None), None);
+    }
+}